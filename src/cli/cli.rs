@@ -12,12 +12,55 @@ pub struct Cli {
 pub enum Commands {
     Init,
     Add {
-        path: String,
+        /// Files, directories or glob patterns to stage
+        #[arg(required_unless_present_any = ["all", "update"])]
+        paths: Vec<String>,
+        /// Stage every change in the working tree, including deletions
+        #[arg(short = 'A', long, conflicts_with = "update")]
+        all: bool,
+        /// Restage modified and deleted tracked files only
+        #[arg(short, long)]
+        update: bool,
     },
     Commit {
-        message: String,
+        /// Commit message; without -m or -F an editor is opened
+        #[arg(short, long, conflicts_with = "file")]
+        message: Option<String>,
+        /// Read the commit message from a file (`-` for stdin)
+        #[arg(short = 'F', long)]
+        file: Option<std::path::PathBuf>,
+        /// Commit even if the tree is unchanged from the parent
+        #[arg(long)]
+        allow_empty: bool,
+        /// Replace the tip commit, keeping its parents
+        #[arg(long)]
+        amend: bool,
+    },
+    Rm {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Only remove from the index, keeping the working-tree files
+        #[arg(long)]
+        cached: bool,
+        /// Allow removing directories
+        #[arg(short)]
+        r: bool,
+        /// Remove even if the files have uncommitted changes
+        #[arg(short, long)]
+        force: bool,
+    },
+    Mv {
+        /// Sources followed by the destination
+        #[arg(required = true, num_args = 2..)]
+        paths: Vec<String>,
+        /// Overwrite an existing destination
+        #[arg(short, long)]
+        force: bool,
+    },
+    Log {
+        /// Revision to start from (defaults to HEAD)
+        rev: Option<String>,
     },
-    Log,
     Status,
     Diff,
     Node {
@@ -43,6 +86,118 @@ pub enum Commands {
         port: Option<u16>,
     },
     Checkout {
-        hash: String,
+        rev: String,
+        /// Discard local changes that would otherwise block the checkout
+        #[arg(short, long)]
+        force: bool,
+    },
+    RevParse {
+        rev: String,
+    },
+    Branch {
+        #[command(subcommand)]
+        action: Option<BranchAction>,
+    },
+    Switch {
+        target: String,
+        /// Create the branch at HEAD before switching to it
+        #[arg(short = 'c', long)]
+        create: bool,
+        /// Detach HEAD at the given revision instead of switching branches
+        #[arg(long)]
+        detach: bool,
+        /// Discard local changes that would otherwise block the switch
+        #[arg(short, long)]
+        force: bool,
+    },
+    Gc,
+    Fsck,
+    Prune {
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long)]
+        grace_days: Option<u64>,
+    },
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    Clean {
+        /// Only list what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        #[arg(short, long)]
+        force: bool,
+        /// Also remove ignored files
+        #[arg(short = 'x')]
+        ignored: bool,
+    },
+    Restore {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Restore the index instead of the working tree
+        #[arg(short = 'S', long)]
+        staged: bool,
+        /// Commit to restore from (defaults to the index, or HEAD with --staged)
+        #[arg(short, long)]
+        source: Option<String>,
+    },
+    Reset {
+        /// Commit to reset to (defaults to HEAD)
+        rev: Option<String>,
+        /// Only move the current branch
+        #[arg(long, conflicts_with_all = ["mixed", "hard"])]
+        soft: bool,
+        /// Move the branch and reset the index (the default)
+        #[arg(long, conflicts_with = "hard")]
+        mixed: bool,
+        /// Move the branch and reset the index and working tree
+        #[arg(long)]
+        hard: bool,
+    },
+    CheckIgnore {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Show the pattern that matched each path
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+        #[arg(long)]
+        global: bool,
+    },
+    Unset {
+        key: String,
+        #[arg(long)]
+        global: bool,
+    },
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum BranchAction {
+    List,
+    Create {
+        name: String,
+        /// Revision to start the branch at (defaults to HEAD)
+        start: Option<String>,
+    },
+    Delete {
+        name: String,
+        #[arg(long)]
+        force: bool,
+    },
+    Rename {
+        old: String,
+        new: String,
     },
 }
\ No newline at end of file
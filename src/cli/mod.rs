@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod cli;
 pub use cli::Cli;
\ No newline at end of file
@@ -1,13 +1,73 @@
 use crate::core::FsObjectStore;
 use crate::index::Index;
+use crate::repo::ignore::IgnoreRules;
+use crate::repo::pathspec::Pathspec;
+use crate::repo::worktree;
 use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fs;
 use std::path::Path;
 
-pub fn execute(repo: &Path, path: &str) -> Result<()> {
+/// Stages the files matched by `pathspecs`; directories are walked
+/// recursively, skipping ignored paths, and tracked files missing from disk
+/// are staged as deletions.
+/// `all` stages the whole tree when no pathspec is given; `update` only
+/// restages files that are already tracked.
+pub fn execute<S: AsRef<str>>(repo: &Path, pathspecs: &[S], all: bool, update: bool) -> Result<()> {
     let store = FsObjectStore::new(repo);
     let mut idx = Index::load(repo)?;
-    let oid = idx.add_file(repo, Path::new(path), &store)?;
+
+    let specs = if pathspecs.is_empty() {
+        if !all && !update {
+            return Err(anyhow::anyhow!("nothing specified, nothing added"));
+        }
+        vec![Pathspec::Prefix(String::new())]
+    } else {
+        pathspecs.iter().map(|s| Pathspec::parse(s.as_ref())).collect::<Result<Vec<_>>>()?
+    };
+
+    // Globs can match anywhere, so they need the whole tree; plain paths only
+    // need their own subtree walked.
+    let mut on_disk = BTreeSet::new();
+    let roots: Vec<&str> = if specs.iter().any(|s| matches!(s, Pathspec::Glob(_))) {
+        vec![""]
+    } else {
+        specs.iter().map(|s| match s {
+            Pathspec::Prefix(p) => p.as_str(),
+            Pathspec::Glob(_) => "",
+        }).collect()
+    };
+    let rules = IgnoreRules::load(repo)?;
+    for root in roots {
+        on_disk.extend(worktree::list_files(repo, root, Some(&rules))?);
+    }
+
+    let candidates: BTreeSet<Vec<u8>> = on_disk.iter().chain(idx.entries.keys()).cloned().collect();
+    for (spec, raw) in specs.iter().zip(pathspecs.iter()) {
+        if !candidates.iter().any(|p| spec.matches(p)) {
+            return Err(anyhow::anyhow!("pathspec '{}' did not match any files", raw.as_ref()));
+        }
+    }
+
+    for path in candidates.iter().filter(|p| specs.iter().any(|s| s.matches(p))) {
+        let tracked = idx.entries.contains_key(path);
+        // Tracked files stay tracked even if an ignore rule now matches them.
+        let abs = repo.join(worktree::from_repo_path(path));
+        if on_disk.contains(path) || (tracked && fs::symlink_metadata(&abs).is_ok_and(|m| worktree::is_tracked_kind(&m))) {
+            if update && !tracked {
+                continue;
+            }
+            let previous = idx.entries.get(path).map(|e| e.oid.clone());
+            let oid = idx.add_file(repo, &worktree::from_repo_path(path), &store)?;
+            if previous.as_deref() != Some(oid.as_str()) {
+                println!("added {} -> {}", worktree::display_path(path), oid);
+            }
+        } else if tracked {
+            idx.entries.remove(path);
+            println!("removed {}", worktree::display_path(path));
+        }
+    }
+
     idx.save(repo)?;
-    println!("added {} -> {}", path, oid);
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,81 @@
+use crate::cli::cli::BranchAction;
+use crate::core::FsObjectStore;
+use crate::repo::refs;
+use crate::repo::revparse::resolve_commit;
+use crate::repo::sync::is_descendant;
+use anyhow::Result;
+use std::path::Path;
+
+pub fn execute(repo: &Path, action: Option<&BranchAction>) -> Result<()> {
+    match action.unwrap_or(&BranchAction::List) {
+        BranchAction::List => {
+            let current = refs::current_branch(repo)?;
+            for (name, oid) in refs::list_branches(repo)? {
+                let marker = if current.as_deref() == Some(name.as_str()) { '*' } else { ' ' };
+                println!("{} {} {}", marker, name, &oid.to_hex()[..12]);
+            }
+        }
+        BranchAction::Create { name, start } => {
+            let oid = create(repo, name, start.as_deref().unwrap_or("HEAD"))?;
+            println!("Created branch {} at {}", name, &oid.to_hex()[..12]);
+        }
+        BranchAction::Delete { name, force } => {
+            delete(repo, name, *force)?;
+            println!("Deleted branch {}", name);
+        }
+        BranchAction::Rename { old, new } => {
+            rename(repo, old, new)?;
+            println!("Renamed branch {} to {}", old, new);
+        }
+    }
+    Ok(())
+}
+
+/// Creates `name` pointing at the commit `start` resolves to.
+pub fn create(repo: &Path, name: &str, start: &str) -> Result<crate::core::Oid> {
+    refs::check_branch_name(name)?;
+    if refs::read_branch(repo, name)?.is_some() {
+        return Err(anyhow::anyhow!("branch {} already exists", name));
+    }
+    let store = FsObjectStore::new(repo);
+    let (oid, _) = resolve_commit(repo, &store, start)?;
+    refs::write_branch(repo, name, &oid)?;
+    Ok(oid)
+}
+
+/// Deletes a branch. Without `force`, the branch must be merged into HEAD so
+/// no commits are lost.
+pub fn delete(repo: &Path, name: &str, force: bool) -> Result<()> {
+    if refs::current_branch(repo)?.as_deref() == Some(name) {
+        return Err(anyhow::anyhow!("cannot delete the checked-out branch {}", name));
+    }
+    let tip = refs::read_branch(repo, name)?.ok_or_else(|| anyhow::anyhow!("branch {} not found", name))?;
+
+    if !force {
+        let store = FsObjectStore::new(repo);
+        let merged = match refs::head_commit(repo)? {
+            Some(head) => is_descendant(&store, &head.to_hex(), &tip.to_hex()),
+            None => false,
+        };
+        if !merged {
+            return Err(anyhow::anyhow!("branch {} is not merged into HEAD; use --force to delete it anyway", name));
+        }
+    }
+    refs::remove_branch(repo, name)
+}
+
+/// Renames a branch, keeping HEAD on it if it was checked out.
+pub fn rename(repo: &Path, old: &str, new: &str) -> Result<()> {
+    let tip = refs::read_branch(repo, old)?.ok_or_else(|| anyhow::anyhow!("branch {} not found", old))?;
+    refs::check_branch_name(new)?;
+    if refs::read_branch(repo, new)?.is_some() {
+        return Err(anyhow::anyhow!("branch {} already exists", new));
+    }
+
+    refs::write_branch(repo, new, &tip)?;
+    refs::remove_branch(repo, old)?;
+    if refs::current_branch(repo)?.as_deref() == Some(old) {
+        refs::set_head_branch(repo, new)?;
+    }
+    Ok(())
+}
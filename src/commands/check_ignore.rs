@@ -0,0 +1,24 @@
+use crate::repo::ignore::IgnoreRules;
+use anyhow::Result;
+use std::path::Path;
+
+/// Prints each path that is ignored. With `verbose`, also prints the file and
+/// pattern that decided it, including negated patterns that re-include a path.
+pub fn execute<S: AsRef<str>>(repo: &Path, paths: &[S], verbose: bool) -> Result<()> {
+    let rules = IgnoreRules::load(repo)?;
+    for raw in paths {
+        let raw = raw.as_ref();
+        let path = raw.trim_start_matches("./").trim_end_matches('/');
+        let is_dir = raw.ends_with('/') || repo.join(path).is_dir();
+
+        match rules.check(path, is_dir) {
+            Some(m) if verbose => {
+                let source = m.source.strip_prefix(repo).unwrap_or(&m.source);
+                println!("{}:{}\t{}", source.display(), m.pattern, raw);
+            }
+            Some(m) if m.ignored => println!("{}", raw),
+            _ => {}
+        }
+    }
+    Ok(())
+}
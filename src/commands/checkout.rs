@@ -1,38 +1,200 @@
 use anyhow::Result;
-use crate::core::store::FsObjectStore;
+use crate::core::repo_dir;
+use crate::core::store::{flatten_tree, FsObjectStore};
 use crate::core::types::{Object, Oid};
-use std::path::Path;
+use crate::index::{Index, StatData};
+use crate::repo::object_store::ObjectStore;
+use crate::repo::refs;
+use crate::repo::revparse::resolve_commit;
+use crate::repo::worktree;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
 
-pub fn execute(repo: &Path, commit_hash: &str) -> Result<()> {
+/// Checks out `rev`. A branch name attaches HEAD to that branch; any other
+/// revision detaches HEAD at the commit it resolves to.
+pub fn execute(repo: &Path, rev: &str, force: bool) -> Result<()> {
     let store = FsObjectStore::new(repo);
-    let commit_oid = Oid::from_hex(commit_hash)?;
-    
-    if let Some(Object::Commit(commit)) = store.get(&commit_oid)? {
-        let tree_oid = Oid::from_hex(&commit.tree)?;
-        checkout_tree(repo, &store, &tree_oid)?;
-    } else {
-        return Err(anyhow::anyhow!("Commit not found: {}", commit_hash));
-    }
-    
+    let (target, _) = resolve_commit(repo, &store, rev)?;
+    let branch = refs::check_branch_name(rev)
+        .ok()
+        .and_then(|_| refs::read_branch(repo, rev).ok().flatten())
+        .map(|_| rev);
+
+    let current = refs::head_commit(repo)?;
+    switch_trees(repo, &store, current.as_ref(), &target, force)?;
+
+    match branch {
+        Some(name) => {
+            refs::set_head_branch(repo, name)?;
+            println!("Switched to branch {}", name);
+        }
+        None => {
+            refs::detach_head(repo, &target)?;
+            println!("HEAD is now detached at {}", &target.to_hex()[..12]);
+        }
+    }
     Ok(())
 }
 
-fn checkout_tree(repo: &Path, store: &FsObjectStore, tree_oid: &Oid) -> Result<()> {
-    if let Some(Object::Tree(entries)) = store.get(tree_oid)? {
-        for entry in entries {
-            let path = repo.join(&entry.name);
-            
-            // For now, we only support blobs directly in the root or sub-folders
-            // (Our tree model is simple: a list of all files in the commit)
-            if let Some(Object::Blob(content)) = store.get(&entry.oid)? {
-                if let Some(parent) = path.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::write(&path, content)?;
-                println!("Updated: {}", entry.name);
+/// Moves the working directory and index from commit `from` (`None` for an
+/// empty tree) to commit `to`, without touching HEAD.
+///
+/// Only paths that differ between the two trees are rewritten or deleted, so
+/// local edits to other files carry over. If a path that has to change has
+/// uncommitted edits in the working directory or index, nothing is touched
+/// and an error lists the files, unless `force` is set; `force` instead
+/// resets every tracked path to `to`. Untracked files and directories in the
+/// way of a write block the switch even with `force`.
+pub fn switch_trees(repo: &Path, store: &dyn ObjectStore, from: Option<&Oid>, to: &Oid, force: bool) -> Result<()> {
+    let old = match from {
+        Some(oid) => commit_files(store, oid)?,
+        None => HashMap::new(),
+    };
+    let new = commit_files(store, to)?;
+    let mut index = Index::load(repo)?;
+
+    let mut paths: BTreeSet<Vec<u8>> = old.keys().chain(new.keys()).cloned().collect();
+    if force {
+        paths.extend(index.entries.keys().cloned());
+    }
+
+    let mut changes = Vec::new();
+    let mut conflicts = Vec::new();
+    for path in paths {
+        // Files are compared by content and mode, so a mode-only change is a change too.
+        let before = old.get(&path).copied();
+        let after = new.get(&path).copied();
+        if before == after && !force {
+            continue;
+        }
+        let staged = index.entries.get(&path).and_then(|e| Some((Oid::from_hex(&e.oid).ok()?, e.mode)));
+        let on_disk = worktree_state(repo, &index, &path)?;
+
+        if force {
+            if on_disk != after || staged != after {
+                changes.push(path);
             }
+            continue;
+        }
+        let clean = |state: Option<(Oid, u32)>| state == before || state == after;
+        if !clean(on_disk) || !clean(staged) {
+            conflicts.push(path);
+        } else {
+            changes.push(path);
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(anyhow::anyhow!(
+            "checkout would overwrite local changes to:\n  {}\ncommit them first, or use --force to discard them",
+            conflicts.iter().map(|p| worktree::display_path(p)).collect::<Vec<_>>().join("\n  ")
+        ));
+    }
+
+    let (writes, removals): (Vec<_>, Vec<_>) = changes.into_iter().partition(|p| new.contains_key(p));
+    // Under --force, files that were only staged go back to being untracked.
+    let removed: HashSet<&[u8]> = removals.iter().filter(|p| old.contains_key(*p)).map(|p| p.as_slice()).collect();
+    let mut blocked = Vec::new();
+    for path in &writes {
+        if is_blocked(repo, path, &removed)? {
+            blocked.push(worktree::display_path(path));
+        }
+    }
+    if !blocked.is_empty() {
+        return Err(anyhow::anyhow!(
+            "checkout would overwrite untracked files or directories at:\n  {}\nmove or remove them first",
+            blocked.join("\n  ")
+        ));
+    }
+
+    // Every blob is read before the working tree is touched, so a missing or
+    // damaged object cannot leave a half-switched checkout behind.
+    let mut contents = Vec::new();
+    for path in &writes {
+        let (oid, mode) = new[path];
+        let mut blob = store
+            .open_blob(&oid)?
+            .ok_or_else(|| anyhow::anyhow!("blob {} for {} is missing", oid, worktree::display_path(path)))?;
+        let mut tmp = tempfile::tempfile_in(repo_dir(repo))?;
+        io::copy(&mut blob, &mut tmp)?;
+        tmp.seek(SeekFrom::Start(0))?;
+        contents.push((path, oid, mode, tmp));
+    }
+
+    // Deletions first, so a file can make way for a directory of the same name.
+    for path in &removals {
+        if removed.contains(path.as_slice()) {
+            worktree::remove_file(repo, path)?;
+            println!("Removed: {}", worktree::display_path(path));
         }
+        index.entries.remove(path);
     }
+    for (path, oid, mode, mut tmp) in contents {
+        worktree::write_file(repo, path, mode, &mut tmp)?;
+        index.record_checkout(repo, path, &oid, mode)?;
+        println!("Updated: {}", worktree::display_path(path));
+    }
+
+    index.save(repo)?;
     Ok(())
 }
+
+/// Blob id and mode of a working-tree file, taken from its index entry
+/// without hashing when the cached stat data still matches.
+fn worktree_state(repo: &Path, index: &Index, path: &[u8]) -> Result<Option<(Oid, u32)>> {
+    if let (Some(entry), Ok(meta)) = (index.entries.get(path), fs::symlink_metadata(repo.join(worktree::from_repo_path(path)))) {
+        if worktree::is_tracked_kind(&meta) && index.stat_matches(entry, &StatData::from_metadata(&meta)?) {
+            return Ok(Some((Oid::from_hex(&entry.oid)?, entry.mode)));
+        }
+    }
+    worktree::file_state(repo, path)
+}
+
+/// Whether writing `path` would clobber something the switch does not own:
+/// a file or symlink where one of its parent directories has to go, or a
+/// directory holding files that are not being removed.
+fn is_blocked(repo: &Path, path: &[u8], removed: &HashSet<&[u8]>) -> Result<bool> {
+    for (i, _) in path.iter().enumerate().filter(|(_, &b)| b == b'/') {
+        let parent = &path[..i];
+        match fs::symlink_metadata(repo.join(worktree::from_repo_path(parent))) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => return Ok(!removed.contains(parent)),
+            Err(_) => return Ok(false),
+        }
+    }
+
+    let abs = repo.join(worktree::from_repo_path(path));
+    if !fs::symlink_metadata(&abs).is_ok_and(|m| m.is_dir()) {
+        return Ok(false);
+    }
+    for entry in walkdir::WalkDir::new(&abs).min_depth(1) {
+        let entry = entry?;
+        if !entry.file_type().is_dir() && !removed.contains(worktree::to_repo_path(entry.path().strip_prefix(repo)?).as_slice()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Path -> (blob id, mode) for every file in a commit's tree.
+pub fn commit_files(store: &dyn ObjectStore, commit_oid: &Oid) -> Result<HashMap<Vec<u8>, (Oid, u32)>> {
+    let commit = match store.get(commit_oid)? {
+        Some(Object::Commit(c)) => c,
+        _ => return Err(anyhow::anyhow!("Commit not found: {}", commit_oid)),
+    };
+    let tree_oid = Oid::from_hex(&commit.tree)?;
+    Ok(flatten_tree(store, &tree_oid)?
+        .into_iter()
+        .map(|e| (e.name, (e.oid, e.mode)))
+        .collect())
+}
+
+/// Writes blob `oid` to the working tree at `path` with `mode`.
+pub fn write_worktree_file(repo: &Path, store: &dyn ObjectStore, path: &[u8], oid: &Oid, mode: u32) -> Result<()> {
+    let mut content = store
+        .open_blob(oid)?
+        .ok_or_else(|| anyhow::anyhow!("blob {} for {} is missing", oid, worktree::display_path(path)))?;
+    worktree::write_file(repo, path, mode, &mut content)
+}
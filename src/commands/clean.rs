@@ -0,0 +1,35 @@
+use crate::index::Index;
+use crate::repo::ignore::IgnoreRules;
+use crate::repo::worktree;
+use anyhow::Result;
+use std::path::Path;
+
+pub fn execute(repo: &Path, dry_run: bool, force: bool, include_ignored: bool) -> Result<()> {
+    if !dry_run && !force {
+        return Err(anyhow::anyhow!("refusing to clean without --force; use --dry-run to preview"));
+    }
+    for path in clean(repo, include_ignored, dry_run)? {
+        if dry_run {
+            println!("Would remove {}", worktree::display_path(&path));
+        } else {
+            println!("Removing {}", worktree::display_path(&path));
+        }
+    }
+    Ok(())
+}
+
+/// Removes untracked files, skipping ignored ones unless `include_ignored`.
+/// Returns the affected paths; with `dry_run` nothing is deleted.
+pub fn clean(repo: &Path, include_ignored: bool, dry_run: bool) -> Result<Vec<Vec<u8>>> {
+    let index = Index::load(repo)?;
+    let rules = IgnoreRules::load(repo)?;
+    let files = worktree::list_files(repo, "", (!include_ignored).then_some(&rules))?;
+
+    let untracked: Vec<Vec<u8>> = files.into_iter().filter(|p| !index.entries.contains_key(p)).collect();
+    if !dry_run {
+        for path in &untracked {
+            worktree::remove_file(repo, path)?;
+        }
+    }
+    Ok(untracked)
+}
@@ -1,55 +1,107 @@
-use crate::core::{write_tree, Commit, FsObjectStore, Object};
+use crate::core::{write_tree, Commit, FsObjectStore, Object, Oid};
 use crate::index::Index;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::refs;
 use anyhow::Result;
 use std::fs;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
 
+const EDIT_HINT: &str = "\n# Enter the commit message. Lines starting with '#' are ignored,\n# and an empty message aborts the commit.\n";
 
-pub fn execute(repo: &Path, message: &str) -> Result<()> {
+/// Records the index as a new commit on top of HEAD.
+///
+/// Without `message` the configured editor is opened. A commit whose tree
+/// matches its parent's is refused unless `allow_empty` is set. `amend`
+/// replaces the tip commit instead, keeping its parents and author and, when
+/// no message is given, offering its message for editing.
+pub fn execute(repo: &Path, message: Option<&str>, allow_empty: bool, amend: bool) -> Result<()> {
     let store = FsObjectStore::new(repo);
     let idx = Index::load(repo)?;
-    
-    if idx.is_empty() {
-        return Err(anyhow::anyhow!("nothing to commit"));
-    }
+    let head = refs::head_commit(repo)?;
+
+    let amended = match (amend, &head) {
+        (false, _) => None,
+        (true, Some(oid)) => match store.get(oid)? {
+            Some(Object::Commit(c)) => Some(c),
+            _ => return Err(anyhow::anyhow!("commit {} not found", oid)),
+        },
+        (true, None) => return Err(anyhow::anyhow!("nothing to amend: there are no commits yet")),
+    };
+    let parents = match &amended {
+        Some(c) => c.parents.clone(),
+        None => head.iter().map(Oid::to_hex).collect(),
+    };
 
     let tree_oid = write_tree(repo, &idx, &store)?;
-    let parent = get_head_commit(repo)?;
-    let parents = parent.into_iter().collect::<Vec<_>>();
+    // Merges record history even when their tree matches the first parent.
+    if !allow_empty && parents.len() < 2 && tree_oid == parent_tree(&store, parents.first())? {
+        return Err(anyhow::anyhow!(match amend {
+            true => "amending would leave the commit empty (use --allow-empty to allow it)",
+            false => "nothing to commit (use --allow-empty to commit anyway)",
+        }));
+    }
+
+    let message = match message {
+        Some(m) => m.to_string(),
+        None => edit_message(repo, amended.as_ref().map_or("", |c| c.message.as_str()))?,
+    };
+    if message.trim().is_empty() {
+        return Err(anyhow::anyhow!("aborting commit due to empty commit message"));
+    }
 
-    let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let signature = crate::repo::config::user_signature(repo)?;
     let commit = Commit {
         tree: tree_oid,
         parents,
-        author: "minigit <minigit@example.com>".to_string(),
-        message: message.to_string(),
-        timestamp: ts,
+        author: amended.map_or_else(|| signature.clone(), |c| c.author),
+        committer: signature,
+        message,
     };
 
     let oid = store.put(&Object::Commit(commit))?;
-    update_head(repo, &oid)?;
+    refs::update_head(repo, &oid)?;
 
-    println!("Committed {}", oid);
+    match refs::current_branch(repo)? {
+        Some(branch) => println!("Committed {} on {}", oid, branch),
+        None => println!("Committed {} (detached HEAD)", oid),
+    }
     Ok(())
 }
 
-fn get_head_commit(repo: &Path) -> Result<Option<String>> {
-    let headp = crate::core::head_file(repo);
-    if headp.exists() {
-        let s = fs::read_to_string(&headp)?;
-        if s.trim().is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(s.trim().to_string()))
-        }
-    } else {
-        Ok(None)
+/// Tree id of `parent`, or of the empty tree for a root commit.
+fn parent_tree(store: &dyn ObjectStore, parent: Option<&String>) -> Result<String> {
+    let Some(parent) = parent else {
+        return Ok(crate::core::tree_oid(&[]).to_hex());
+    };
+    match store.get(&Oid::from_hex(parent)?)? {
+        Some(Object::Commit(c)) => Ok(c.tree),
+        _ => Err(anyhow::anyhow!("commit {} not found", parent)),
     }
 }
 
-fn update_head(repo: &Path, oid: &crate::core::Oid) -> Result<()> {
-    fs::create_dir_all(crate::core::refs_heads_dir(repo))?;
-    fs::write(crate::core::head_file(repo), oid.to_hex())?;
-    Ok(())
-}
\ No newline at end of file
+/// Opens the editor on `.rvc/COMMIT_EDITMSG`, seeded with `initial`, and
+/// returns what was saved without comment lines.
+fn edit_message(repo: &Path, initial: &str) -> Result<String> {
+    let path = crate::core::repo_dir(repo).join("COMMIT_EDITMSG");
+    fs::write(&path, format!("{}{}", initial, EDIT_HINT))?;
+
+    let editor = crate::repo::config::editor(repo)?;
+    // The editor may carry arguments, so it goes through the shell.
+    #[cfg(unix)]
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(&path)
+        .status();
+    #[cfg(not(unix))]
+    let status = std::process::Command::new("cmd").arg("/C").arg(&editor).arg(&path).status();
+
+    let status = status.map_err(|e| anyhow::anyhow!("could not run editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("editor '{}' exited with {}", editor, status));
+    }
+    let text = fs::read_to_string(&path)?;
+    let kept: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+    Ok(format!("{}\n", kept.join("\n").trim()))
+}
@@ -0,0 +1,44 @@
+use crate::cli::cli::ConfigAction;
+use crate::repo::config::{global_config_path, load_config, repo_config_path, Config};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+pub fn execute(repo: &Path, action: &ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Get { key } => {
+            let cfg = load_config(repo)?;
+            match cfg.get(key) {
+                Some(value) => println!("{}", value),
+                None => return Err(anyhow::anyhow!("config key not set: {}", key)),
+            }
+        }
+        ConfigAction::Set { key, value, global } => {
+            let path = config_file(repo, *global)?;
+            let mut cfg = Config::load(&path)?;
+            cfg.set(key, value)?;
+            cfg.save(&path)?;
+        }
+        ConfigAction::Unset { key, global } => {
+            let path = config_file(repo, *global)?;
+            let mut cfg = Config::load(&path)?;
+            if !cfg.unset(key) {
+                return Err(anyhow::anyhow!("config key not set: {}", key));
+            }
+            cfg.save(&path)?;
+        }
+        ConfigAction::List => {
+            for (key, value) in load_config(repo)?.iter() {
+                println!("{}={}", key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn config_file(repo: &Path, global: bool) -> Result<PathBuf> {
+    if global {
+        global_config_path().ok_or_else(|| anyhow::anyhow!("cannot locate the global config file"))
+    } else {
+        Ok(repo_config_path(repo))
+    }
+}
@@ -1,7 +1,12 @@
-use crate::core::{FsObjectStore, Oid};
+use crate::core::{flatten_tree, FsObjectStore, Object, Oid};
 use crate::index::Index;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::rename::{detect_renames, Rename, DEFAULT_RENAME_THRESHOLD};
+use crate::repo::worktree;
 use anyhow::Result;
+use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 
 pub fn execute(repo: &Path) -> Result<()> {
@@ -9,85 +14,117 @@ pub fn execute(repo: &Path) -> Result<()> {
     let index = Index::load(repo)?;
     
     // Get the last commit to compare against
-    let head_oid = get_head_commit_oid(repo)?;
+    let head_oid = crate::repo::refs::head_commit(repo)?;
+    let committed = match &head_oid {
+        Some(oid) => commit_files(&store, oid)?,
+        None => HashMap::new(),
+    };
     
-    println!("Diff against: {}", head_oid.as_deref().unwrap_or("(no commits)"));
+    println!("Diff against: {}", head_oid.map(|o| o.to_hex()).as_deref().unwrap_or("(no commits)"));
     println!();
 
-    for (file_path, index_entry) in &index.entries {
-        show_file_diff(repo, &store, file_path, &index_entry.oid, head_oid.as_deref())?;
+    // Staged renames are diffed against the file's old path.
+    let mut added = Vec::new();
+    for (path, entry) in &index.entries {
+        if !committed.contains_key(path) {
+            added.push((path.clone(), Oid::from_hex(&entry.oid)?));
+        }
+    }
+    let deleted: Vec<(Vec<u8>, Oid)> = committed
+        .iter()
+        .filter(|(path, _)| !index.entries.contains_key(*path))
+        .map(|(path, (oid, _))| (path.clone(), *oid))
+        .collect();
+    added.sort();
+    let renames = detect_renames(&store, &deleted, &added, DEFAULT_RENAME_THRESHOLD)?;
+    let renamed: HashMap<&[u8], &Rename> = renames.iter().map(|r| (r.to.as_slice(), r)).collect();
+
+    let mut paths: Vec<&Vec<u8>> = index.entries.keys().collect();
+    paths.sort();
+    for file_path in paths {
+        let rename = renamed.get(file_path.as_slice()).copied();
+        let old_path = rename.map_or(file_path.as_slice(), |r| r.from.as_slice());
+        show_file_diff(repo, &store, old_path, file_path, committed.get(old_path).copied(), rename)?;
     }
     
     Ok(())
 }
 
-fn get_head_commit_oid(repo: &Path) -> Result<Option<String>> {
-    let head_file = crate::core::head_file(repo);
-    if !head_file.exists() {
-        return Ok(None);
-    }
-    
-    let content = fs::read_to_string(head_file)?;
-    if content.trim().is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(content.trim().to_string()))
+fn commit_files(store: &dyn ObjectStore, commit_oid: &Oid) -> Result<HashMap<Vec<u8>, (Oid, u32)>> {
+    let mut files = HashMap::new();
+    if let Some(Object::Commit(commit)) = store.get(commit_oid)? {
+        for entry in flatten_tree(store, &Oid::from_hex(&commit.tree)?)? {
+            files.insert(entry.name, (entry.oid, entry.mode));
+        }
     }
+    Ok(files)
 }
+
 fn show_file_diff(
     repo: &Path,
-    store: &FsObjectStore,
-    file_path: &str,
-    _indexed_oid: &str,
-    head_commit_oid: Option<&str>,
+    store: &dyn ObjectStore,
+    old_path: &[u8],
+    file_path: &[u8],
+    committed: Option<(Oid, u32)>,
+    rename: Option<&Rename>,
 ) -> Result<()> {
-    let file_path_buf = repo.join(file_path);
+    let file_path_buf = repo.join(worktree::from_repo_path(file_path));
+    let (old_path, file_path) = (worktree::display_path(old_path), worktree::display_path(file_path));
     
     // Skip if file doesn't exist
-    if !file_path_buf.exists() {
-        return Ok(());
+    let meta = match fs::symlink_metadata(&file_path_buf) {
+        Ok(meta) => meta,
+        Err(_) => return Ok(()),
+    };
+    let mode = worktree::file_mode(&meta);
+    let committed_oid = committed.map(|(oid, _)| oid);
+
+    if let Some(r) = rename {
+        println!("rename from {}", worktree::display_path(&r.from));
+        println!("rename to {}", worktree::display_path(&r.to));
+        println!("similarity {}%", r.score);
+    }
+    let old_mode = committed.map(|(_, m)| m).filter(|&m| m != mode);
+    if let Some(old_mode) = old_mode {
+        println!("diff a/{} b/{}", old_path, file_path);
+        println!("old mode {:o}", old_mode);
+        println!("new mode {:o}", mode);
     }
-    let current_content_bytes = fs::read(&file_path_buf)?;
-    let current_content = match String::from_utf8(current_content_bytes.clone()) {
-        Ok(text) => text,
-        Err(_) => {
-            println!("--- a/{}", file_path);
-            println!("+++ b/{}", file_path);
-            println!("Binary files differ");
-            println!();
+
+    // Hashing streams the file, so unchanged files are skipped without loading either side
+    if let Some(oid) = committed_oid {
+        if crate::core::blob_oid_reader(worktree::open_content(&file_path_buf)?)? == oid {
+            if rename.is_some() || old_mode.is_some() {
+                println!();
+            }
             return Ok(());
         }
-    };
+    }
 
-    let current_lines: Vec<String> = current_content.lines().map(|s| s.to_string()).collect();
-    
-    // Get the committed version 
-    let committed_content_bytes = if let Some(commit_oid) = head_commit_oid {
-        get_file_content_from_commit_bytes(commit_oid, file_path, store).ok()
-    } else {
-        None
+    let current_lines = match read_text_lines(worktree::open_content(&file_path_buf)?)? {
+        Some(lines) => lines,
+        None => {
+            print_binary_differ(&old_path, &file_path);
+            return Ok(());
+        }
     };
-    
-    let committed_lines: Vec<String> = if let Some(bytes) = committed_content_bytes {
-        match String::from_utf8(bytes) {
-            Ok(text) => text.lines().map(|s| s.to_string()).collect(),
-            Err(_) => {
-                println!("--- a/{}", file_path);
-                println!("+++ b/{}", file_path);
-                println!("Binary files differ");
-                println!();
+
+    let committed_lines: Vec<String> = match committed_oid.map(|oid| store.open_blob(&oid)).transpose()?.flatten() {
+        Some(reader) => match read_text_lines(reader)? {
+            Some(lines) => lines,
+            None => {
+                print_binary_differ(&old_path, &file_path);
                 return Ok(());
             }
-        }
-    } else {
-        Vec::new() // File didn't exist in last commit
+        },
+        None => Vec::new(), // File didn't exist in last commit
     };
     
     // Simple diff algorithm - compare line by line
     let diff = compute_diff(&committed_lines, &current_lines);
     
     if !diff.is_empty() {
-        println!("--- a/{}", file_path);
+        println!("--- a/{}", old_path);
         println!("+++ b/{}", file_path);
         
         for change in diff {
@@ -102,25 +139,30 @@ fn show_file_diff(
     
     Ok(())
 }
-//Helper function
-fn get_file_content_from_commit_bytes(commit_oid: &str, file_path: &str, store: &FsObjectStore) -> Result<Vec<u8>> {
-    let commit_oid = Oid::from_hex(commit_oid)?;
-    
-    if let Some(crate::core::Object::Commit(commit)) = store.get(&commit_oid)? {
-        let tree_oid = Oid::from_hex(&commit.tree)?;
-        
-        if let Some(crate::core::Object::Tree(entries)) = store.get(&tree_oid)? {
-            for entry in entries {
-                if entry.name == file_path {
-                    if let Some(crate::core::Object::Blob(content)) = store.get(&entry.oid)? {
-                        return Ok(content);
-                    }
-                }
-            }
-        }
+
+/// Files larger than this are reported like binary files instead of being
+/// read into memory and diffed line by line.
+pub const MAX_TEXT_DIFF_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Reads text content as lines, or `None` if it is larger than
+/// `MAX_TEXT_DIFF_SIZE`, contains a NUL byte or is not valid UTF-8.
+fn read_text_lines<R: Read>(reader: R) -> Result<Option<Vec<String>>> {
+    let mut buf = Vec::new();
+    reader.take(MAX_TEXT_DIFF_SIZE + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > MAX_TEXT_DIFF_SIZE || buf.contains(&0) {
+        return Ok(None);
     }
-    
-    Err(anyhow::anyhow!("File not found in commit: {}", file_path))
+    match String::from_utf8(buf) {
+        Ok(text) => Ok(Some(text.lines().map(|s| s.to_string()).collect())),
+        Err(_) => Ok(None),
+    }
+}
+
+fn print_binary_differ(old_path: &str, file_path: &str) {
+    println!("--- a/{}", old_path);
+    println!("+++ b/{}", file_path);
+    println!("Binary files differ");
+    println!();
 }
 
 
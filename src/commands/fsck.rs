@@ -0,0 +1,203 @@
+use crate::core::pack::load_packs;
+use crate::core::{decode_object, finalize_oid, object_hasher, packs_dir, FsObjectStore, Object, Oid};
+use crate::index::Index;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::sync::get_local_refs;
+use crate::repo::worktree::display_path;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// (referrer, expected kind, target)
+type Link = (String, &'static str, Oid);
+
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub checked: usize,
+    pub corrupt: Vec<String>,
+    pub missing: Vec<String>,
+    pub dangling: Vec<String>,
+}
+
+impl FsckReport {
+    /// Dangling objects are only reported; corruption and missing links are errors.
+    pub fn has_errors(&self) -> bool {
+        !self.corrupt.is_empty() || !self.missing.is_empty()
+    }
+}
+
+pub fn execute(repo: &Path) -> Result<()> {
+    let report = check(repo, &FsObjectStore::new(repo))?;
+
+    for line in report.corrupt.iter().chain(&report.missing).chain(&report.dangling) {
+        println!("{}", line);
+    }
+    println!(
+        "Checked {} objects: {} corrupt, {} missing, {} dangling",
+        report.checked,
+        report.corrupt.len(),
+        report.missing.len(),
+        report.dangling.len()
+    );
+
+    if report.has_errors() {
+        return Err(anyhow::anyhow!("repository is damaged"));
+    }
+    Ok(())
+}
+
+pub fn check(repo: &Path, store: &dyn ObjectStore) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+    // Corrupt objects are reported once, not again as missing from every referrer.
+    let mut bad: HashSet<Oid> = HashSet::new();
+    let mut kinds: HashMap<Oid, &'static str> = HashMap::new();
+    let mut links: Vec<Link> = Vec::new();
+
+    for pack in load_packs(&packs_dir(repo))? {
+        if let Err(e) = pack.verify() {
+            report.corrupt.push(format!("corrupt pack {}: {}", pack.pack_path().display(), e));
+        }
+    }
+
+    for oid in store.list_oids()? {
+        report.checked += 1;
+        bad.insert(oid);
+        let kind = match hash_object(store, &oid) {
+            Ok((kind, actual)) if actual == oid => kind,
+            Ok((_, actual)) => {
+                report.corrupt.push(format!("corrupt {}: content hashes to {}", oid, actual));
+                continue;
+            }
+            Err(e) => {
+                report.corrupt.push(format!("corrupt {}: {}", oid, e));
+                continue;
+            }
+        };
+
+        // Blobs have no links, so only trees and commits are loaded and parsed.
+        if kind == "blob" {
+            bad.remove(&oid);
+            kinds.insert(oid, kind);
+            continue;
+        }
+        let decoded = store
+            .get_raw(&oid)
+            .and_then(|raw| decode_object(&raw.ok_or_else(|| anyhow::anyhow!("listed but unreadable"))?));
+        match decoded {
+            Ok(obj) => {
+                bad.remove(&oid);
+                record_object(oid, obj, &mut kinds, &mut links, &mut report);
+            }
+            Err(e) => report.corrupt.push(format!("corrupt {} ({}): {}", oid, kind, e)),
+        }
+    }
+
+    // Staged but uncommitted content is still wanted.
+    for (path, entry) in Index::load(repo)?.entries {
+        match Oid::from_hex(&entry.oid) {
+            Ok(oid) => links.push((format!("index {}", display_path(&path)), "blob", oid)),
+            Err(_) => report.corrupt.push(format!("bad index entry {}: {}", display_path(&path), entry.oid)),
+        }
+    }
+
+    for (name, hash) in get_local_refs(repo) {
+        match Oid::from_hex(&hash) {
+            Ok(oid) => links.push((format!("ref {}", name), "commit", oid)),
+            Err(_) => report.corrupt.push(format!("bad ref {}: {}", name, hash)),
+        }
+    }
+
+    let mut referenced = HashSet::new();
+    for (from, expected, target) in links {
+        referenced.insert(target);
+        if bad.contains(&target) {
+            continue;
+        }
+        match kinds.get(&target) {
+            None => report.missing.push(format!("missing {} {} (referenced by {})", expected, target, from)),
+            Some(kind) if *kind != expected => report.corrupt.push(format!(
+                "broken link from {} to {}: expected {}, found {}",
+                from, target, expected, kind
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let mut dangling: Vec<_> = kinds.iter().filter(|(oid, _)| !referenced.contains(*oid)).collect();
+    dangling.sort_by_key(|(oid, _)| oid.to_hex());
+    for (oid, kind) in dangling {
+        report.dangling.push(format!("dangling {} {}", kind, oid));
+    }
+
+    Ok(report)
+}
+
+/// Streams an object's raw bytes through the hasher, returning its kind and
+/// the id its content actually hashes to.
+fn hash_object(store: &dyn ObjectStore, oid: &Oid) -> Result<(&'static str, Oid)> {
+    let mut reader = store
+        .open_raw(oid)?
+        .ok_or_else(|| anyhow::anyhow!("listed but unreadable"))?;
+
+    let mut header = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == 0 || header.len() > 16 {
+            break;
+        }
+        header.push(byte[0]);
+    }
+    let kind = match header.as_slice() {
+        b"blob" => "blob",
+        b"tree" => "tree",
+        b"commit" => "commit",
+        _ => return Err(anyhow::anyhow!("malformed object header")),
+    };
+
+    let mut hasher = object_hasher(kind);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok((kind, finalize_oid(&hasher)))
+}
+
+fn record_object(
+    oid: Oid,
+    obj: Object,
+    kinds: &mut HashMap<Oid, &'static str>,
+    links: &mut Vec<Link>,
+    report: &mut FsckReport,
+) {
+    match obj {
+        Object::Blob(_) => {
+            kinds.insert(oid, "blob");
+        }
+        Object::Tree(entries) => {
+            kinds.insert(oid, "tree");
+            for e in entries {
+                let expected = if e.is_dir() { "tree" } else { "blob" };
+                links.push((format!("tree {}", oid), expected, e.oid));
+            }
+        }
+        Object::Commit(c) => {
+            kinds.insert(oid, "commit");
+            match Oid::from_hex(&c.tree) {
+                Ok(t) => links.push((format!("commit {}", oid), "tree", t)),
+                Err(_) => report.corrupt.push(format!("corrupt {}: invalid tree id {}", oid, c.tree)),
+            }
+            for p in &c.parents {
+                match Oid::from_hex(p) {
+                    Ok(p) => links.push((format!("commit {}", oid), "commit", p)),
+                    Err(_) => report.corrupt.push(format!("corrupt {}: invalid parent id {}", oid, p)),
+                }
+            }
+        }
+    }
+}
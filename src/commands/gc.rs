@@ -0,0 +1,54 @@
+use crate::core::pack::{plan_deltas, write_pack};
+use crate::core::{packs_dir, FsObjectStore, Oid};
+use crate::repo::sync::get_local_refs;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+pub fn execute(repo: &Path) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    let pruned = super::prune::prune(repo, &store, super::prune::DEFAULT_GRACE, false)?;
+    if !pruned.is_empty() {
+        println!("Pruned {} unreachable objects", pruned.len());
+    }
+
+    let loose = store.loose_oids()?;
+    if loose.is_empty() {
+        println!("Nothing to pack");
+        return Ok(());
+    }
+
+    // Repack everything (existing packs plus loose objects) into a single pack.
+    let mut seen = HashSet::new();
+    let mut objects: Vec<Oid> = store.packed_oids()?;
+    objects.extend(loose.iter().copied());
+    objects.retain(|o| seen.insert(*o));
+
+    let heads: Vec<Oid> = get_local_refs(repo).values().filter_map(|h| Oid::from_hex(h).ok()).collect();
+    let bases = plan_deltas(&store, &heads, None)?;
+    let dir = packs_dir(repo);
+    let new_pack = write_pack(&dir, &store, &objects, &bases)?;
+    let new_idx = new_pack.with_extension("idx");
+
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let is_pack_file = matches!(path.extension().and_then(|e| e.to_str()), Some("pack") | Some("idx"));
+        if is_pack_file && path != new_pack && path != new_idx {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    for oid in &loose {
+        store.remove_loose(oid)?;
+    }
+
+    println!(
+        "Packed {} objects ({} loose, {} delta candidates) into {}",
+        objects.len(),
+        loose.len(),
+        bases.len(),
+        new_pack.file_name().unwrap_or_default().to_string_lossy()
+    );
+    Ok(())
+}
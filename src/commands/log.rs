@@ -1,13 +1,14 @@
-use crate::core::{FsObjectStore, Oid};
+use crate::core::{FsObjectStore, Oid, Signature};
+use crate::repo::object_store::ObjectStore;
+use crate::repo::revparse::resolve_commit;
 use anyhow::Result;
 use std::collections::VecDeque;
-use std::fs;
 use std::path::Path;
 
 
-pub fn execute(repo: &Path) -> Result<()> {
+pub fn execute(repo: &Path, rev: Option<&str>) -> Result<()> {
     let store = FsObjectStore::new(repo);
-    let logs = get_logs(repo, &store)?;
+    let logs = get_logs(repo, &store, rev)?;
     
     for entry in logs {
         println!("{}", entry);
@@ -16,29 +17,26 @@ pub fn execute(repo: &Path) -> Result<()> {
     Ok(())
 }
 
-fn get_logs(repo: &Path, store: &FsObjectStore) -> Result<Vec<String>> {
-    let headp = crate::core::head_file(repo);
-    if !headp.exists() {
-        return Ok(vec![]);
-    }
-
-    let head = fs::read_to_string(headp)?.trim().to_string();
-    if head.is_empty() {
-        return Ok(vec![]);
-    }
-
-    let bytes = hex::decode(&head)?;
-    let oid = Oid::from(&bytes[..]);
+fn get_logs(repo: &Path, store: &dyn ObjectStore, rev: Option<&str>) -> Result<Vec<String>> {
+    let oid = match rev {
+        Some(rev) => resolve_commit(repo, store, rev)?.0,
+        None => match crate::repo::refs::head_commit(repo)? {
+            Some(oid) => oid,
+            None => return Ok(vec![]),
+        },
+    };
     let mut out = Vec::new();
     let mut queue = VecDeque::new();
     queue.push_back(oid);
 
     while let Some(cur) = queue.pop_front() {
         if let Some(crate::core::Object::Commit(c)) = store.get(&cur)? {
-            out.push(format!(
-                "commit {}\nAuthor: {}\nDate: {}\n\n    {}\n",
-                cur, c.author, c.timestamp, c.message
-            ));
+            let mut entry = format!("commit {}\nAuthor: {}\n", cur, c.author.ident());
+            if c.committer.ident() != c.author.ident() {
+                entry.push_str(&format!("Commit: {}\n", c.committer.ident()));
+            }
+            entry.push_str(&format!("Date:   {}\n\n    {}\n", format_date(&c.author), c.message));
+            out.push(entry);
 
             for parent in c.parents.iter() {
                 let pb = hex::decode(parent)?;
@@ -48,4 +46,8 @@ fn get_logs(repo: &Path, store: &FsObjectStore) -> Result<Vec<String>> {
     }
 
     Ok(out)
-}
\ No newline at end of file
+}
+/// Formats a signature time in its own timezone, e.g. `Tue Nov 14 22:13:20 2023 +0000`.
+fn format_date(sig: &Signature) -> String {
+    sig.datetime().format("%a %b %e %H:%M:%S %Y %z").to_string()
+}
@@ -5,4 +5,17 @@ pub mod log;
 pub mod status;
 pub mod diff;
 pub mod checkout;
+pub mod gc;
+pub mod fsck;
+pub mod prune;
+pub mod config;
+pub mod rev_parse;
+pub mod branch;
+pub mod switch;
+pub mod clean;
+pub mod check_ignore;
+pub mod rm;
+pub mod mv;
+pub mod restore;
+pub mod reset;
 pub use crate::cli::cli::Commands;
\ No newline at end of file
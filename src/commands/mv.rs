@@ -0,0 +1,113 @@
+use crate::index::Index;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Moves or renames tracked files and directories, on disk and in the index.
+/// With several sources, or when `dest` is an existing directory, each
+/// source is moved into `dest`. Nothing is touched if any move is invalid.
+pub fn execute<S: AsRef<str>>(repo: &Path, sources: &[S], dest: &str, force: bool) -> Result<()> {
+    let mut idx = Index::load(repo)?;
+    let dest = normalize(dest)?;
+    let into_dir = repo.join(&dest).is_dir();
+    if sources.len() > 1 && !into_dir {
+        return Err(anyhow::anyhow!("destination '{}' is not a directory", dest));
+    }
+
+    let mut moves = Vec::new();
+    let mut renames = Vec::new();
+    let mut targets = HashSet::new();
+    for src in sources {
+        let src = normalize(src.as_ref())?;
+        if src.is_empty() {
+            return Err(anyhow::anyhow!("cannot move the repository root"));
+        }
+        let target = if into_dir {
+            let name = src.rsplit('/').next().unwrap_or(&src);
+            if dest.is_empty() { name.to_string() } else { format!("{}/{}", dest, name) }
+        } else {
+            dest.clone()
+        };
+
+        if target == src || target.starts_with(&format!("{}/", src)) {
+            return Err(anyhow::anyhow!("cannot move '{}' into itself", src));
+        }
+        if !targets.insert(target.clone()) {
+            return Err(anyhow::anyhow!("more than one source would be moved to '{}'", target));
+        }
+        if !repo.join(&src).exists() {
+            return Err(anyhow::anyhow!("bad source '{}': no such file or directory", src));
+        }
+
+        let under = format!("{}/", src);
+        let tracked: Vec<Vec<u8>> = idx
+            .entries
+            .keys()
+            .filter(|p| p.as_slice() == src.as_bytes() || p.starts_with(under.as_bytes()))
+            .cloned()
+            .collect();
+        if tracked.is_empty() {
+            return Err(anyhow::anyhow!("'{}' is not under version control", src));
+        }
+
+        if !force {
+            let target_under = format!("{}/", target);
+            let clash = repo.join(&target).exists()
+                || idx.entries.keys().any(|p| p.as_slice() == target.as_bytes() || p.starts_with(target_under.as_bytes()));
+            if clash {
+                return Err(anyhow::anyhow!("destination '{}' already exists; use -f to overwrite it", target));
+            }
+        }
+
+        for old in tracked {
+            let new = [target.as_bytes(), &old[src.len()..]].concat();
+            renames.push((old, new));
+        }
+        moves.push((src, target));
+    }
+
+    for (src, target) in &moves {
+        let to = repo.join(target);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if force && to.is_file() {
+            fs::remove_file(&to)?;
+        }
+        fs::rename(repo.join(src), &to)?;
+        println!("renamed {} -> {}", src, target);
+    }
+    for (old, new) in renames {
+        if let Some(mut entry) = idx.entries.remove(&old) {
+            entry.path = new.clone();
+            idx.entries.insert(new, entry);
+        }
+    }
+    idx.save(repo)?;
+    Ok(())
+}
+
+/// Resolves `.` and `..` in a path relative to the repository root; `.` on
+/// its own is the root. Paths that leave the working directory or point into
+/// `.rvc` are rejected.
+fn normalize(path: &str) -> Result<String> {
+    let outside = || anyhow::anyhow!("'{}' is outside the working directory", path);
+    if Path::new(path).is_absolute() {
+        return Err(outside());
+    }
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or_else(outside)?;
+            }
+            p => parts.push(p),
+        }
+    }
+    if parts.first() == Some(&".rvc") {
+        return Err(outside());
+    }
+    Ok(parts.join("/"))
+}
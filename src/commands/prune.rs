@@ -0,0 +1,124 @@
+use crate::core::{repo_dir, FsObjectStore, Object, Oid};
+use crate::index::Index;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::sync::get_local_refs;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Unreachable loose objects younger than this are kept, so objects from an
+/// in-flight `add` or sync are not swept before a ref points at them.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+pub fn execute(repo: &Path, dry_run: bool, grace_days: Option<u64>) -> Result<()> {
+    let grace = grace_days
+        .map(|d| Duration::from_secs(d * 24 * 60 * 60))
+        .unwrap_or(DEFAULT_GRACE);
+    let store = FsObjectStore::new(repo);
+    let pruned = prune(repo, &store, grace, dry_run)?;
+
+    for oid in &pruned {
+        if dry_run {
+            println!("would remove {}", oid);
+        } else {
+            println!("removed {}", oid);
+        }
+    }
+    println!("{} unreachable objects {}", pruned.len(), if dry_run { "would be removed" } else { "removed" });
+    Ok(())
+}
+
+/// Removes loose objects that nothing reaches and that are older than
+/// `grace`. Returns the OIDs removed (or that would be, with `dry_run`).
+pub fn prune(repo: &Path, store: &FsObjectStore, grace: Duration, dry_run: bool) -> Result<Vec<Oid>> {
+    let reachable = reachable_objects(repo, store)?;
+    let now = SystemTime::now();
+
+    let mut pruned = Vec::new();
+    for oid in store.loose_oids()? {
+        if reachable.contains(&oid) {
+            continue;
+        }
+        let age = store
+            .loose_modified(&oid)?
+            .and_then(|m| now.duration_since(m).ok())
+            .unwrap_or_default();
+        if age < grace {
+            continue;
+        }
+        if !dry_run {
+            store.remove_loose(&oid)?;
+        }
+        pruned.push(oid);
+    }
+    pruned.sort_by_key(|o| o.to_hex());
+    Ok(pruned)
+}
+
+/// Marks every object reachable from HEAD, refs (heads, tags, stash, ...),
+/// reflog entries and the index.
+///
+/// Only commits and trees are read. Blobs named by a tree or the index are
+/// marked without being loaded, so large files in history cost nothing.
+pub fn reachable_objects(repo: &Path, store: &dyn ObjectStore) -> Result<HashSet<Oid>> {
+    let mut queue: Vec<Oid> = root_hashes(repo)?
+        .iter()
+        .filter_map(|h| Oid::from_hex(h).ok())
+        .collect();
+
+    let mut reachable = HashSet::new();
+    for entry in Index::load(repo)?.entries.values() {
+        if let Ok(oid) = Oid::from_hex(&entry.oid) {
+            reachable.insert(oid);
+        }
+    }
+
+    while let Some(oid) = queue.pop() {
+        if !reachable.insert(oid) {
+            continue;
+        }
+        match store.get(&oid)? {
+            Some(Object::Commit(c)) => {
+                queue.extend(c.parents.iter().filter_map(|p| Oid::from_hex(p).ok()));
+                queue.extend(Oid::from_hex(&c.tree).ok());
+            }
+            Some(Object::Tree(entries)) => {
+                for e in entries {
+                    if e.is_dir() {
+                        queue.push(e.oid);
+                    } else {
+                        reachable.insert(e.oid);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(reachable)
+}
+
+fn root_hashes(repo: &Path) -> Result<Vec<String>> {
+    let mut roots: Vec<String> = get_local_refs(repo).into_values().collect();
+
+    // Any ref namespace and any reflog line may name a commit worth keeping.
+    for dir in [repo_dir(repo).join("refs"), repo_dir(repo).join("logs")] {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let content = fs::read_to_string(entry.path()).unwrap_or_default();
+            roots.extend(
+                content
+                    .split_whitespace()
+                    .filter(|t| t.len() == 64 && t.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .map(|t| t.to_string()),
+            );
+        }
+    }
+    Ok(roots)
+}
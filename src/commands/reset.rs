@@ -0,0 +1,62 @@
+use super::checkout::{commit_files, switch_trees};
+use crate::core::FsObjectStore;
+use crate::index::Index;
+use crate::repo::refs;
+use crate::repo::revparse::resolve_commit;
+use crate::repo::worktree;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// How much of the repository `rvc reset` moves to the target commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only the current branch (or detached HEAD).
+    Soft,
+    /// The branch and the index; working-tree files are kept.
+    Mixed,
+    /// The branch, the index and every tracked working-tree file.
+    Hard,
+}
+
+/// Points the current branch, or a detached HEAD, at `rev` and brings the
+/// index and working tree along as `mode` says.
+pub fn execute(repo: &Path, rev: &str, mode: ResetMode) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    let (target, commit) = resolve_commit(repo, &store, rev)?;
+    let current = refs::head_commit(repo)?;
+
+    match mode {
+        ResetMode::Soft => {}
+        ResetMode::Mixed => {
+            let files = commit_files(&store, &target)?;
+            let mut idx = Index::load(repo)?;
+            let paths: BTreeSet<Vec<u8>> = files.keys().chain(idx.entries.keys()).cloned().collect();
+            for path in paths {
+                idx.reset_entry(&path, files.get(&path).copied());
+            }
+            idx.save(repo)?;
+        }
+        ResetMode::Hard => {
+            // switch_trees leaves files that were only staged on disk as
+            // untracked; a hard reset discards them as well.
+            let mut staged_only: Vec<Vec<u8>> = Index::load(repo)?.entries.into_keys().collect();
+            let target_files = commit_files(&store, &target)?;
+            let current_files = match &current {
+                Some(oid) => commit_files(&store, oid)?,
+                None => Default::default(),
+            };
+            staged_only.retain(|p| !target_files.contains_key(p) && !current_files.contains_key(p));
+
+            switch_trees(repo, &store, current.as_ref(), &target, true)?;
+            for path in staged_only {
+                worktree::remove_file(repo, &path)?;
+                println!("Removed: {}", worktree::display_path(&path));
+            }
+        }
+    }
+
+    refs::update_head(repo, &target)?;
+    println!("HEAD is now at {} {}", &target.to_hex()[..12], commit.message.lines().next().unwrap_or(""));
+    Ok(())
+}
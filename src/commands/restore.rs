@@ -0,0 +1,73 @@
+use super::checkout::{commit_files, write_worktree_file};
+use crate::core::{FsObjectStore, Oid};
+use crate::index::Index;
+use crate::repo::pathspec::Pathspec;
+use crate::repo::refs;
+use crate::repo::revparse::resolve_commit;
+use crate::repo::worktree;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Restores the paths matched by `pathspecs` from `source`.
+///
+/// With `staged`, index entries are reset (from HEAD by default) and the
+/// working tree is left alone; this is how changes are unstaged. Otherwise
+/// working-tree files are overwritten (from the index by default), and
+/// tracked files missing from `source` are deleted.
+pub fn execute<S: AsRef<str>>(repo: &Path, pathspecs: &[S], staged: bool, source: Option<&str>) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    let mut idx = Index::load(repo)?;
+    let specs = pathspecs.iter().map(|s| Pathspec::parse(s.as_ref())).collect::<Result<Vec<_>>>()?;
+
+    let from_index = source.is_none() && !staged;
+    let files: HashMap<Vec<u8>, (Oid, u32)> = match source {
+        Some(rev) => commit_files(&store, &resolve_commit(repo, &store, rev)?.0)?,
+        None if staged => match refs::head_commit(repo)? {
+            Some(head) => commit_files(&store, &head)?,
+            None => HashMap::new(),
+        },
+        None => idx
+            .entries
+            .values()
+            .map(|e| Ok((e.path.clone(), (Oid::from_hex(&e.oid)?, e.mode))))
+            .collect::<Result<_>>()?,
+    };
+
+    let candidates: BTreeSet<&Vec<u8>> = files.keys().chain(idx.entries.keys()).collect();
+    for (spec, raw) in specs.iter().zip(pathspecs.iter()) {
+        if !candidates.iter().any(|p| spec.matches(p)) {
+            return Err(anyhow::anyhow!("pathspec '{}' did not match any tracked files", raw.as_ref()));
+        }
+    }
+    let paths: Vec<Vec<u8>> = candidates.into_iter().filter(|p| specs.iter().any(|s| s.matches(p))).cloned().collect();
+
+    for path in &paths {
+        let target = files.get(path).copied();
+        if staged {
+            idx.reset_entry(path, target);
+            continue;
+        }
+        match target {
+            Some((oid, mode)) => {
+                if worktree::file_state(repo, path)? != Some((oid, mode)) {
+                    write_worktree_file(repo, &store, path, &oid, mode)?;
+                    println!("Restored: {}", worktree::display_path(path));
+                }
+                // Only a restore from the index may refresh its cached stat data.
+                if from_index {
+                    idx.record_checkout(repo, path, &oid, mode)?;
+                }
+            }
+            None => {
+                worktree::remove_file(repo, path)?;
+                println!("Removed: {}", worktree::display_path(path));
+            }
+        }
+    }
+
+    if staged || from_index {
+        idx.save(repo)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,11 @@
+use crate::core::FsObjectStore;
+use crate::repo::revparse::resolve_revision;
+use anyhow::Result;
+use std::path::Path;
+
+/// Prints the full object id a revision resolves to.
+pub fn execute(repo: &Path, rev: &str) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    println!("{}", resolve_revision(repo, &store, rev)?);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::core::{flatten_tree, FsObjectStore, Object, Oid};
+use crate::index::Index;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::pathspec::Pathspec;
+use crate::repo::{refs, worktree};
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Stops tracking the files matched by `pathspecs` and, unless `cached`,
+/// deletes them from the working directory. Directories need `recursive`.
+///
+/// Without `force`, files whose content would be lost are refused: with
+/// `cached`, ones whose staged content matches neither HEAD nor the file on
+/// disk; otherwise, any with staged changes or local modifications.
+pub fn execute<S: AsRef<str>>(repo: &Path, pathspecs: &[S], cached: bool, recursive: bool, force: bool) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    let mut idx = Index::load(repo)?;
+
+    let mut targets = BTreeSet::new();
+    for raw in pathspecs {
+        let raw = raw.as_ref();
+        let spec = Pathspec::parse(raw)?;
+        let matched: Vec<&Vec<u8>> = idx.entries.keys().filter(|p| spec.matches(p)).collect();
+        if matched.is_empty() {
+            return Err(anyhow::anyhow!("pathspec '{}' did not match any tracked files", raw));
+        }
+        if let Pathspec::Prefix(prefix) = &spec {
+            if !recursive && matched.iter().any(|p| p.as_slice() != prefix.as_bytes()) {
+                return Err(anyhow::anyhow!("not removing '{}' recursively without -r", raw));
+            }
+        }
+        targets.extend(matched.into_iter().cloned());
+    }
+
+    if !force {
+        let head = head_files(repo, &store)?;
+        let mut refused = Vec::new();
+        for path in &targets {
+            let staged = Oid::from_hex(&idx.entries[path].oid).ok();
+            let committed = head.get(path).copied();
+            let on_disk = worktree::file_oid(repo, path)?;
+            let modified = on_disk.is_some() && on_disk != staged;
+
+            let unsafe_to_remove = if cached {
+                staged != committed && modified
+            } else {
+                staged != committed || modified
+            };
+            if unsafe_to_remove {
+                refused.push(worktree::display_path(path));
+            }
+        }
+        if !refused.is_empty() {
+            return Err(anyhow::anyhow!(
+                "the following files have uncommitted changes:\n  {}\nuse --cached to keep them on disk, or -f to force removal",
+                refused.join("\n  ")
+            ));
+        }
+    }
+
+    for path in &targets {
+        idx.entries.remove(path);
+        if !cached {
+            worktree::remove_file(repo, path)?;
+        }
+        println!("rm '{}'", worktree::display_path(path));
+    }
+    idx.save(repo)?;
+    Ok(())
+}
+
+fn head_files(repo: &Path, store: &dyn ObjectStore) -> Result<HashMap<Vec<u8>, Oid>> {
+    let mut files = HashMap::new();
+    if let Some(head) = refs::head_commit(repo)? {
+        if let Some(Object::Commit(c)) = store.get(&head)? {
+            for entry in flatten_tree(store, &Oid::from_hex(&c.tree)?)? {
+                files.insert(entry.name, entry.oid);
+            }
+        }
+    }
+    Ok(files)
+}
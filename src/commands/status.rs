@@ -1,5 +1,10 @@
-use crate::core::{FsObjectStore, Oid};
-use crate::index::Index;
+use crate::core::{flatten_tree, FsObjectStore, Oid};
+use crate::index::{Index, StatData};
+use crate::repo::ignore::IgnoreRules;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::rename::{detect_renames, DEFAULT_RENAME_THRESHOLD};
+use crate::repo::refs::{self, Head};
+use crate::repo::worktree;
 use anyhow::Result;
 use std::fs;
 use std::path::Path;
@@ -8,6 +13,10 @@ use std::collections::HashMap;
 pub fn execute(repo: &Path) -> Result<()> {
     let store = FsObjectStore::new(repo);
     let status = get_status(repo, &store)?;
+    match refs::read_head(repo)? {
+        Head::Branch(name) => println!("On branch {}", name),
+        Head::Detached(oid) => println!("HEAD detached at {}", &oid.to_hex()[..12]),
+    }
     print_status(&status);
     Ok(())
 }
@@ -19,107 +28,127 @@ pub struct Status {
     pub untracked_files: Vec<String>,
 }
 
-fn get_status(repo: &Path, store: &FsObjectStore) -> Result<Status> {
+pub fn get_status(repo: &Path, store: &dyn ObjectStore) -> Result<Status> {
     let mut status = Status::default();
-    let index = Index::load(repo)?;
-    let head_oid = get_head_commit_oid(repo)?;
+    let mut index = Index::load(repo)?;
+    let head_oid = refs::head_commit(repo)?;
     let last_commit_tree = if let Some(oid) = head_oid {
-        get_commit_tree(&oid, store)?
+        get_commit_tree(&oid.to_hex(), store)?
     } else {
         HashMap::new() 
     };
-    let working_files = find_working_directory_files(repo)?;
+    let rules = IgnoreRules::load(repo)?;
+    let working_files = worktree::list_files(repo, "", Some(&rules))?;
     
+    let mut added = Vec::new();
     for indexed_file in index.entries.keys() {
-        if let Some(commit_oid) = last_commit_tree.get(indexed_file) {
-            let index_oid = &index.entries[indexed_file].oid;
-            if index_oid != commit_oid {
-                status.staged_changes.push(format!("modified: {}", indexed_file));
+        if let Some((commit_oid, commit_mode)) = last_commit_tree.get(indexed_file) {
+            let entry = &index.entries[indexed_file];
+            if &entry.oid != commit_oid {
+                status.staged_changes.push(format!("modified: {}", worktree::display_path(indexed_file)));
+            } else if entry.mode != *commit_mode {
+                status.staged_changes.push(mode_change(indexed_file, *commit_mode, entry.mode));
             }
         } else {
-            status.staged_changes.push(format!("new file: {}", indexed_file));
+            added.push((indexed_file.clone(), Oid::from_hex(&index.entries[indexed_file].oid)?));
+        }
+    }
+    let mut deleted = Vec::new();
+    for (committed_file, (commit_oid, _)) in &last_commit_tree {
+        if !index.entries.contains_key(committed_file) {
+            deleted.push((committed_file.clone(), Oid::from_hex(commit_oid)?));
         }
     }
+    added.sort();
+    deleted.sort();
 
-    
-    for file_path in working_files {
-        if index.entries.contains_key(&file_path) {
-     
-            let current_oid = compute_file_oid(repo, &file_path)?;
-            let indexed_oid = &index.entries[&file_path].oid;
-            
-            if &current_oid != indexed_oid {
-                status.unstaged_changes.push(format!("modified: {}", file_path));
+    let renames = detect_renames(store, &deleted, &added, DEFAULT_RENAME_THRESHOLD)?;
+    for rename in &renames {
+        status.staged_changes.push(format!(
+            "renamed: {} -> {}",
+            worktree::display_path(&rename.from),
+            worktree::display_path(&rename.to)
+        ));
+    }
+    for (path, _) in added.iter().filter(|(p, _)| !renames.iter().any(|r| &r.to == p)) {
+        status.staged_changes.push(format!("new file: {}", worktree::display_path(path)));
+    }
+    for (path, _) in deleted.iter().filter(|(p, _)| !renames.iter().any(|r| &r.from == p)) {
+        status.staged_changes.push(format!("deleted: {}", worktree::display_path(path)));
+    }
+    status.staged_changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+
+    // Tracked files are checked even when an ignore rule matches them. Files
+    // whose stat data still matches the index are not rehashed; files that
+    // hash unchanged get their cached stat data refreshed.
+    let mut tracked: Vec<Vec<u8>> = index.entries.keys().cloned().collect();
+    tracked.sort();
+    let mut refreshed = false;
+    for file_path in &tracked {
+        let meta = match fs::symlink_metadata(repo.join(worktree::from_repo_path(file_path))) {
+            Ok(meta) if worktree::is_tracked_kind(&meta) => meta,
+            _ => {
+                status.unstaged_changes.push(format!("deleted: {}", worktree::display_path(file_path)));
+                continue;
             }
+        };
+        let stat = StatData::from_metadata(&meta)?;
+        if index.stat_matches(&index.entries[file_path], &stat) {
+            continue;
+        }
+        let current_oid = compute_file_oid(repo, file_path)?;
+        let current_mode = worktree::file_mode(&meta);
+        let entry = index.entries.get_mut(file_path).expect("tracked path is in the index");
+        if current_oid != entry.oid {
+            status.unstaged_changes.push(format!("modified: {}", worktree::display_path(file_path)));
+        } else if current_mode != entry.mode {
+            status.unstaged_changes.push(mode_change(file_path, entry.mode, current_mode));
         } else {
-         
-            status.untracked_files.push(file_path);
+            // Saving also moves the index timestamp past racily clean entries.
+            entry.set_stat(&stat);
+            refreshed = true;
         }
     }
-
-    Ok(status)
-}
-
-fn get_head_commit_oid(repo: &Path) -> Result<Option<String>> {
-    let head_file = crate::core::head_file(repo);
-    if !head_file.exists() {
-        return Ok(None);
+    if refreshed {
+        index.save(repo)?;
     }
-    
-    let content = fs::read_to_string(head_file)?;
-    if content.trim().is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(content.trim().to_string()))
+
+    for file_path in working_files {
+        if !index.entries.contains_key(&file_path) {
+            status.untracked_files.push(worktree::display_path(&file_path).into_owned());
+        }
     }
+
+    Ok(status)
 }
 
-fn get_commit_tree(commit_oid: &str, store: &FsObjectStore) -> Result<HashMap<String, String>> {
+fn get_commit_tree(commit_oid: &str, store: &dyn ObjectStore) -> Result<HashMap<Vec<u8>, (String, u32)>> {
     let oid = Oid::from_hex(commit_oid)?;
     let mut tree_map = HashMap::new();
     
     if let Some(crate::core::Object::Commit(commit)) = store.get(&oid)? {
         let tree_oid = Oid::from_hex(&commit.tree)?;
-        if let Some(crate::core::Object::Tree(entries)) = store.get(&tree_oid)? {
-            for entry in entries {
-                tree_map.insert(entry.name, entry.oid.to_hex());
-            }
+        for entry in flatten_tree(store, &tree_oid)? {
+            tree_map.insert(entry.name, (entry.oid.to_hex(), entry.mode));
         }
     }
     
     Ok(tree_map)
 }
 
-fn find_working_directory_files(repo: &Path) -> Result<Vec<String>> {
-    let mut files = Vec::new();
-    
-    for entry in walkdir::WalkDir::new(repo)
-        .min_depth(1)
-        .max_depth(10) 
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
+fn compute_file_oid(repo: &Path, file_path: &[u8]) -> Result<String> {
+    let abs = repo.join(worktree::from_repo_path(file_path));
+    let oid = crate::core::blob_oid_reader(worktree::open_content(&abs)?)?;
+    Ok(oid.to_hex())
+}
 
-        if path.starts_with(repo.join(".rvc")) {
-            continue;
-        }
-        
-        if path.is_file() {
-            if let Ok(relative_path) = path.strip_prefix(repo) {
-                files.push(relative_path.to_string_lossy().to_string());
-            }
-        }
-    }
-    
-    Ok(files)
+fn mode_change(path: &[u8], old: u32, new: u32) -> String {
+    format!("mode changed: {} ({:o} -> {:o})", worktree::display_path(path), old, new)
 }
 
-fn compute_file_oid(repo: &Path, file_path: &str) -> Result<String> {
-    let abs_path = repo.join(file_path);
-    let content = fs::read(&abs_path)?;
-    let oid = crate::core::blob_oid(&content);
-    Ok(oid.to_hex())
+/// The path part of a `"kind: path"` status line.
+fn change_path(change: &str) -> &str {
+    change.split_once(": ").map_or(change, |(_, path)| path)
 }
 
 fn print_status(status: &Status) {
@@ -134,7 +163,7 @@ fn print_status(status: &Status) {
 
     if !status.unstaged_changes.is_empty() {
         println!("Changes not staged for commit:");
-        println!("  (use 'rvc add <file>' to update what will be committed)");
+        println!("  (use 'rvc add <file>' or 'rvc rm <file>' to update what will be committed)");
         for change in &status.unstaged_changes {
             println!("    {}", change);
         }
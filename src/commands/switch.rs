@@ -0,0 +1,39 @@
+use super::checkout::switch_trees;
+use crate::core::FsObjectStore;
+use crate::repo::refs;
+use crate::repo::revparse::resolve_commit;
+use anyhow::Result;
+use std::path::Path;
+
+/// Switches to `target`: an existing branch, a new branch at HEAD with
+/// `create`, or a detached HEAD at any revision with `detach`.
+pub fn execute(repo: &Path, target: &str, create: bool, detach: bool, force: bool) -> Result<()> {
+    let store = FsObjectStore::new(repo);
+    let current = refs::head_commit(repo)?;
+
+    if detach {
+        let (oid, _) = resolve_commit(repo, &store, target)?;
+        switch_trees(repo, &store, current.as_ref(), &oid, force)?;
+        refs::detach_head(repo, &oid)?;
+        println!("HEAD is now detached at {}", &oid.to_hex()[..12]);
+        return Ok(());
+    }
+
+    if create {
+        // On a branch with no commits yet there is nothing to point at.
+        if current.is_some() {
+            super::branch::create(repo, target, "HEAD")?;
+        } else {
+            refs::check_branch_name(target)?;
+        }
+        refs::set_head_branch(repo, target)?;
+        println!("Switched to a new branch {}", target);
+        return Ok(());
+    }
+
+    let tip = refs::read_branch(repo, target)?.ok_or_else(|| anyhow::anyhow!("branch {} not found", target))?;
+    switch_trees(repo, &store, current.as_ref(), &tip, force)?;
+    refs::set_head_branch(repo, target)?;
+    println!("Switched to branch {}", target);
+    Ok(())
+}
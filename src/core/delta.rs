@@ -0,0 +1,147 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+// Delta layout:
+//   base_len varint | target_len varint | ops...
+// Ops:
+//   0x00 offset varint len varint   copy `len` bytes from the base at `offset`
+//   0x01 len varint bytes...        insert the following `len` bytes
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+/// Shortest run of base bytes worth encoding as a copy.
+const BLOCK: usize = 16;
+
+/// Encodes `target` as a sequence of copies from `base` and literal inserts.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, base.len() as u64);
+    write_varint(&mut out, target.len() as u64);
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    if base.len() >= BLOCK {
+        for off in (0..=base.len() - BLOCK).step_by(BLOCK) {
+            blocks.entry(&base[off..off + BLOCK]).or_insert(off);
+        }
+    }
+
+    let mut pending_start = 0;
+    let mut i = 0;
+    while i + BLOCK <= target.len() {
+        let Some(&base_off) = blocks.get(&target[i..i + BLOCK]) else {
+            i += 1;
+            continue;
+        };
+
+        // Extend the match backwards into pending literals, then forwards.
+        let mut start = i;
+        let mut bstart = base_off;
+        while start > pending_start && bstart > 0 && target[start - 1] == base[bstart - 1] {
+            start -= 1;
+            bstart -= 1;
+        }
+        let mut len = i - start + BLOCK;
+        while bstart + len < base.len() && start + len < target.len() && base[bstart + len] == target[start + len] {
+            len += 1;
+        }
+
+        write_insert(&mut out, &target[pending_start..start]);
+        out.push(OP_COPY);
+        write_varint(&mut out, bstart as u64);
+        write_varint(&mut out, len as u64);
+
+        i = start + len;
+        pending_start = i;
+    }
+    write_insert(&mut out, &target[pending_start..]);
+    out
+}
+
+/// Rebuilds the target from `base` and a delta produced by `create_delta`.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_len = read_varint(delta, &mut pos)? as usize;
+    let target_len = read_varint(delta, &mut pos)? as usize;
+    if base_len != base.len() {
+        return Err(anyhow::anyhow!("delta base size mismatch: expected {}, got {}", base_len, base.len()));
+    }
+
+    // Sizes come from the delta itself, which may have arrived from a peer:
+    // reserve fallibly and never let the output grow past what it declared.
+    let mut out = Vec::new();
+    out.try_reserve(target_len)
+        .map_err(|_| anyhow::anyhow!("delta target too large: {} bytes", target_len))?;
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        match op {
+            OP_COPY => {
+                let off = read_varint(delta, &mut pos)? as usize;
+                let len = read_varint(delta, &mut pos)? as usize;
+                let src = off
+                    .checked_add(len)
+                    .and_then(|end| base.get(off..end))
+                    .ok_or_else(|| anyhow::anyhow!("delta copy out of range"))?;
+                extend_within(&mut out, src, target_len)?;
+            }
+            OP_INSERT => {
+                let len = read_varint(delta, &mut pos)? as usize;
+                let src = pos
+                    .checked_add(len)
+                    .and_then(|end| delta.get(pos..end))
+                    .ok_or_else(|| anyhow::anyhow!("delta insert out of range"))?;
+                extend_within(&mut out, src, target_len)?;
+                pos += len;
+            }
+            other => return Err(anyhow::anyhow!("unknown delta op {}", other)),
+        }
+    }
+
+    if out.len() != target_len {
+        return Err(anyhow::anyhow!("delta result size mismatch: expected {}, got {}", target_len, out.len()));
+    }
+    Ok(out)
+}
+
+fn extend_within(out: &mut Vec<u8>, src: &[u8], target_len: usize) -> Result<()> {
+    if out.len() + src.len() > target_len {
+        return Err(anyhow::anyhow!("delta result larger than its recorded size {}", target_len));
+    }
+    out.extend_from_slice(src);
+    Ok(())
+}
+
+fn write_insert(out: &mut Vec<u8>, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    out.push(OP_INSERT);
+    write_varint(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut v = 0u64;
+    let mut shift = 0;
+    loop {
+        let b = *buf.get(*pos).ok_or_else(|| anyhow::anyhow!("truncated delta"))?;
+        *pos += 1;
+        if shift >= 64 {
+            return Err(anyhow::anyhow!("delta varint overflow"));
+        }
+        v |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+        shift += 7;
+    }
+}
@@ -1,40 +1,213 @@
-use super::types::{Commit, Oid, TreeEntry};
+use super::types::{Commit, Oid, Signature, TreeEntry};
 use blake3::Hasher;
-use serde_json;
+use serde::Deserialize;
+use std::io::{self, Read};
 
-pub fn oid_for_bytes(kind: &str, data: &[u8]) -> Oid {
+/// Starts an incremental hash of an object of the given kind; feed it the body.
+pub fn object_hasher(kind: &str) -> Hasher {
     let mut hasher = Hasher::new();
     hasher.update(kind.as_bytes());
     hasher.update(&[0u8]);
+    hasher
+}
+
+pub fn finalize_oid(hasher: &Hasher) -> Oid {
+    Oid::from_bytes(*hasher.finalize().as_bytes())
+}
+
+pub fn oid_for_bytes(kind: &str, data: &[u8]) -> Oid {
+    let mut hasher = object_hasher(kind);
     hasher.update(data);
-    let out = hasher.finalize();
-    let mut b = [0u8; 32];
-    b.copy_from_slice(out.as_bytes());
-    Oid::from_bytes(b)
+    finalize_oid(&hasher)
 }
 
 pub fn blob_oid(content: &[u8]) -> Oid {
     oid_for_bytes("blob", content)
 }
 
+/// Hashes a blob from a reader without holding the whole content in memory.
+pub fn blob_oid_reader<R: Read>(mut reader: R) -> io::Result<Oid> {
+    let mut hasher = object_hasher("blob");
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(finalize_oid(&hasher))
+}
+
+/// Version written in the first line of every tree.
+pub const TREE_FORMAT_VERSION: u32 = 2;
+
+const TREE_MAGIC: &str = "rvc-tree";
+
+/// Encodes a tree as a header line followed by its entries, sorted by name:
+///
+/// ```text
+/// rvc-tree 2
+/// <octal mode> <name>\0<32-byte oid>    (repeated, no separator)
+/// ```
+///
+/// The name is raw bytes ending at the NUL, which cannot occur in a file
+/// name, so names with spaces or newlines survive unchanged.
 pub fn tree_serialize(entries: &[TreeEntry]) -> Vec<u8> {
     let mut es = entries.to_vec();
     es.sort_by(|a, b| a.name.cmp(&b.name));
     
-    let mut out = Vec::new();
+    let mut out = format!("{} {}\n", TREE_MAGIC, TREE_FORMAT_VERSION).into_bytes();
     for e in es.iter() {
-        out.extend(format!("{} {} {}\n", e.mode, e.name, e.oid.to_hex()).as_bytes());
+        out.extend(format!("{:o} ", e.mode).as_bytes());
+        out.extend(&e.name);
+        out.push(0);
+        out.extend(e.oid.as_bytes());
     }
     out
 }
 
+/// Decodes a tree body, accepting the current format and the line-based
+/// `<decimal mode> <name> <hex oid>` text written by earlier versions.
+pub fn tree_deserialize(body: &[u8]) -> anyhow::Result<Vec<TreeEntry>> {
+    let header = format!("{} {}\n", TREE_MAGIC, TREE_FORMAT_VERSION);
+    let Some(mut rest) = body.strip_prefix(header.as_bytes()) else {
+        // Legacy lines start with a decimal mode, never with the magic.
+        if let Some(other) = body.strip_prefix(format!("{} ", TREE_MAGIC).as_bytes()) {
+            let version = other.split(|&b| b == b'\n').next().unwrap_or_default();
+            return Err(anyhow::anyhow!("unsupported tree version {}", String::from_utf8_lossy(version)));
+        }
+        return legacy_tree_deserialize(body);
+    };
+
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ').ok_or_else(|| anyhow::anyhow!("tree entry is missing its mode"))?;
+        let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space])?, 8)?;
+        let nul = rest[space..].iter().position(|&b| b == 0).ok_or_else(|| anyhow::anyhow!("unterminated tree entry name"))? + space;
+        let name = rest[space + 1..nul].to_vec();
+        let oid = rest
+            .get(nul + 1..nul + 33)
+            .ok_or_else(|| anyhow::anyhow!("truncated tree entry {}", String::from_utf8_lossy(&name)))?;
+        entries.push(TreeEntry { mode, name, oid: Oid::from(oid) });
+        rest = &rest[nul + 33..];
+    }
+    Ok(entries)
+}
+
+fn legacy_tree_deserialize(body: &[u8]) -> anyhow::Result<Vec<TreeEntry>> {
+    let mut entries = Vec::new();
+    for line in std::str::from_utf8(body)?.lines() {
+        // Mode and OID never contain spaces, so anything between them is the name.
+        let (mode, rest) = line.split_once(' ').ok_or_else(|| anyhow::anyhow!("malformed tree line: {}", line))?;
+        let (name, oid) = rest.rsplit_once(' ').ok_or_else(|| anyhow::anyhow!("malformed tree line: {}", line))?;
+        entries.push(TreeEntry { mode: mode.parse()?, name: name.as_bytes().to_vec(), oid: Oid::from_hex(oid)? });
+    }
+    Ok(entries)
+}
+
 pub fn tree_oid(entries: &[TreeEntry]) -> Oid {
     let body = tree_serialize(entries);
     oid_for_bytes("tree", &body)
 }
 
+/// Version written in the first line of every canonical commit.
+pub const COMMIT_FORMAT_VERSION: u32 = 2;
+
+const COMMIT_MAGIC: &str = "rvc-commit";
+
+/// Shape of commits written as JSON by the earliest rvc versions.
+#[derive(Deserialize)]
+struct LegacyJsonCommit {
+    tree: String,
+    parents: Vec<String>,
+    author: String,
+    message: String,
+    timestamp: i64,
+}
+
+/// Encodes a commit in the canonical, versioned text format:
+///
+/// ```text
+/// rvc-commit 2
+/// tree <hex>
+/// parent <hex>        (zero or more, in order)
+/// author <name> <<email>> <unix seconds> <+hhmm>
+/// committer <name> <<email>> <unix seconds> <+hhmm>
+///
+/// <message>
+/// ```
+///
+/// Header values are single lines; newlines in them are replaced with
+/// spaces. The message is stored verbatim after the blank line.
 pub fn commit_serialize(c: &Commit) -> Vec<u8> {
-    serde_json::to_vec(c).expect("commit serialize")
+    let one_line = |s: &str| s.replace(['\r', '\n'], " ");
+
+    let mut out = format!("{} {}\n", COMMIT_MAGIC, COMMIT_FORMAT_VERSION);
+    out.push_str(&format!("tree {}\n", one_line(&c.tree)));
+    for p in &c.parents {
+        out.push_str(&format!("parent {}\n", one_line(p)));
+    }
+    out.push_str(&format!("author {}\n", one_line(&c.author.to_string())));
+    out.push_str(&format!("committer {}\n", one_line(&c.committer.to_string())));
+    out.push('\n');
+    out.push_str(&c.message);
+    out.into_bytes()
+}
+
+/// Decodes a commit body, accepting both the canonical format and the JSON
+/// encoding written by earlier rvc versions. JSON commits get the author as
+/// committer.
+pub fn commit_deserialize(body: &[u8]) -> anyhow::Result<Commit> {
+    if !body.starts_with(COMMIT_MAGIC.as_bytes()) {
+        let old: LegacyJsonCommit = serde_json::from_slice(body)?;
+        let author = Signature::from_ident(&old.author, old.timestamp, 0);
+        return Ok(Commit {
+            tree: old.tree,
+            parents: old.parents,
+            committer: author.clone(),
+            author,
+            message: old.message,
+        });
+    }
+
+    let text = std::str::from_utf8(body)?;
+    let (header, message) = text
+        .split_once("\n\n")
+        .ok_or_else(|| anyhow::anyhow!("commit is missing the header terminator"))?;
+    let mut lines = header.lines();
+
+    let version = lines
+        .next()
+        .and_then(|l| l.strip_prefix(COMMIT_MAGIC))
+        .map(str::trim)
+        .ok_or_else(|| anyhow::anyhow!("malformed commit header"))?;
+    if version != COMMIT_FORMAT_VERSION.to_string() {
+        return Err(anyhow::anyhow!("unsupported commit format version {}", version));
+    }
+
+    let mut tree = None;
+    let mut parents = Vec::new();
+    let mut author = None;
+    let mut committer = None;
+    for line in lines {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "tree" => tree = Some(value.to_string()),
+            "parent" => parents.push(value.to_string()),
+            "author" => author = Some(Signature::parse(value)?),
+            "committer" => committer = Some(Signature::parse(value)?),
+            other => return Err(anyhow::anyhow!("unknown commit header: {}", other)),
+        }
+    }
+
+    Ok(Commit {
+        tree: tree.ok_or_else(|| anyhow::anyhow!("commit has no tree"))?,
+        parents,
+        author: author.ok_or_else(|| anyhow::anyhow!("commit has no author"))?,
+        committer: committer.ok_or_else(|| anyhow::anyhow!("commit has no committer"))?,
+        message: message.to_string(),
+    })
 }
 
 pub fn commit_oid(c: &Commit) -> Oid {
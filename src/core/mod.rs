@@ -1,4 +1,6 @@
+pub mod delta;
 pub mod hashing;
+pub mod pack;
 pub mod store;
 pub mod types;
 
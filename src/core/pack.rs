@@ -0,0 +1,483 @@
+use crate::core::delta::{apply_delta, create_delta};
+use crate::core::types::{Object, Oid};
+use crate::repo::object_store::ObjectStore;
+use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Pack layout:
+//   "RPAK" | version u32 | count u32 | entries... | blake3(everything before)
+// Entry:
+//   type u8 | raw_len u64 | zlen u64 | zlib(kind\0body)          full object
+//   type u8 | raw_len u64 | base oid [32] | zlen u64 | zlib(delta)   delta against another entry of the same pack
+//
+// Index layout (entries sorted by OID for binary search):
+//   "RIDX" | version u32 | count u32 | (oid [32] | offset u64)... | pack checksum [32] | blake3(everything before)
+
+const PACK_MAGIC: &[u8; 4] = b"RPAK";
+const IDX_MAGIC: &[u8; 4] = b"RIDX";
+const PACK_VERSION: u32 = 1;
+
+const ENTRY_FULL: u8 = 1;
+const ENTRY_DELTA: u8 = 2;
+
+/// Longest chain of deltas the packer will build before storing an object whole.
+pub const MAX_DELTA_DEPTH: usize = 10;
+
+/// Objects larger than this are always stored whole, so the packer never
+/// holds more than two of them in memory.
+pub const MAX_DELTA_SOURCE: u64 = 64 * 1024 * 1024;
+
+const IDX_ENTRY_LEN: usize = 32 + 8;
+
+/// In-memory view of a `.idx` file, pointing into its `.pack` file.
+pub struct PackIndex {
+    pack_path: PathBuf,
+    entries: Vec<(Oid, u64)>,
+}
+
+impl PackIndex {
+    pub fn load(idx_path: &Path) -> Result<Self> {
+        let buf = fs::read(idx_path).with_context(|| format!("read pack index {}", idx_path.display()))?;
+        if buf.len() < 12 + 64 || &buf[..4] != IDX_MAGIC {
+            return Err(anyhow::anyhow!("malformed pack index: {}", idx_path.display()));
+        }
+
+        let (body, checksum) = buf.split_at(buf.len() - 32);
+        if blake3::hash(body).as_bytes() != checksum {
+            return Err(anyhow::anyhow!("pack index checksum mismatch: {}", idx_path.display()));
+        }
+
+        let version = read_u32(&body[4..8]);
+        if version != PACK_VERSION {
+            return Err(anyhow::anyhow!("unsupported pack index version {}", version));
+        }
+        let count = read_u32(&body[8..12]) as usize;
+        if body.len() != 12 + count * IDX_ENTRY_LEN + 32 {
+            return Err(anyhow::anyhow!("truncated pack index: {}", idx_path.display()));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for rec in body[12..12 + count * IDX_ENTRY_LEN].chunks_exact(IDX_ENTRY_LEN) {
+            entries.push((Oid::from(&rec[..32]), read_u64(&rec[32..])));
+        }
+
+        // The index records the checksum of the pack it was written for; a
+        // pack replaced or truncated underneath it must not be trusted.
+        let pack_path = idx_path.with_extension("pack");
+        let mut pack = File::open(&pack_path).with_context(|| format!("open pack {}", pack_path.display()))?;
+        if pack.metadata()?.len() < 12 + 32 {
+            return Err(anyhow::anyhow!("truncated pack: {}", pack_path.display()));
+        }
+        let mut trailer = [0u8; 32];
+        pack.seek(SeekFrom::End(-32))?;
+        pack.read_exact(&mut trailer)?;
+        if trailer[..] != body[body.len() - 32..] {
+            return Err(anyhow::anyhow!("pack checksum does not match its index: {}", pack_path.display()));
+        }
+
+        Ok(PackIndex { pack_path, entries })
+    }
+
+    /// Rehashes the whole pack and checks it against its trailing checksum.
+    /// Loading only compares the trailer, so this is what catches damage
+    /// inside the entries.
+    pub fn verify(&self) -> Result<()> {
+        let mut f = File::open(&self.pack_path)
+            .with_context(|| format!("open pack {}", self.pack_path.display()))?;
+        let body_len = f
+            .metadata()?
+            .len()
+            .checked_sub(32)
+            .ok_or_else(|| anyhow::anyhow!("truncated pack: {}", self.pack_path.display()))?;
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut BufReader::new(&mut f).take(body_len), &mut hasher)?;
+        let mut trailer = [0u8; 32];
+        f.seek(SeekFrom::Start(body_len))?;
+        f.read_exact(&mut trailer)?;
+        if hasher.finalize().as_bytes() != &trailer {
+            return Err(anyhow::anyhow!("pack checksum mismatch: {}", self.pack_path.display()));
+        }
+        Ok(())
+    }
+
+    pub fn pack_path(&self) -> &Path {
+        &self.pack_path
+    }
+
+    pub fn find(&self, oid: &Oid) -> Option<u64> {
+        self.entries
+            .binary_search_by(|(o, _)| o.as_bytes().cmp(oid.as_bytes()))
+            .ok()
+            .map(|i| self.entries[i].1)
+    }
+
+    pub fn contains(&self, oid: &Oid) -> bool {
+        self.find(oid).is_some()
+    }
+
+    pub fn oids(&self) -> impl Iterator<Item = &Oid> {
+        self.entries.iter().map(|(o, _)| o)
+    }
+
+    /// Reads the uncompressed `kind\0body` bytes of an object stored in this pack,
+    /// resolving delta chains against their bases.
+    pub fn read_raw(&self, oid: &Oid) -> Result<Option<Vec<u8>>> {
+        self.read_raw_at_depth(oid, 0)
+    }
+
+    fn read_raw_at_depth(&self, oid: &Oid, depth: usize) -> Result<Option<Vec<u8>>> {
+        let offset = match self.find(oid) {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+        if depth > MAX_DELTA_DEPTH {
+            return Err(anyhow::anyhow!("delta chain too deep at {}", oid));
+        }
+
+        let mut f = File::open(&self.pack_path)
+            .with_context(|| format!("open pack {}", self.pack_path.display()))?;
+        let body_end = f.metadata()?.len().saturating_sub(32);
+        f.seek(SeekFrom::Start(offset))?;
+
+        let mut header = [0u8; 1 + 8];
+        f.read_exact(&mut header)?;
+        let kind = header[0];
+        let raw_len = read_u64(&header[1..9]);
+
+        let base = match kind {
+            ENTRY_FULL => None,
+            ENTRY_DELTA => {
+                // The packer only builds deltas for objects within MAX_DELTA_SOURCE.
+                if raw_len > MAX_DELTA_SOURCE {
+                    return Err(anyhow::anyhow!("delta entry for {} is too large", oid));
+                }
+                let mut b = [0u8; 32];
+                f.read_exact(&mut b)?;
+                Some(Oid::from_bytes(b))
+            }
+            other => return Err(anyhow::anyhow!("unknown pack entry type {} for {}", other, oid)),
+        };
+
+        let mut zlen = [0u8; 8];
+        f.read_exact(&mut zlen)?;
+        let zlen = read_u64(&zlen);
+        if zlen > body_end.saturating_sub(f.stream_position()?) {
+            return Err(anyhow::anyhow!("pack entry for {} runs past the end of the pack", oid));
+        }
+
+        // The recorded size is not trusted for allocation; inflating one
+        // byte past it is enough to tell an oversized entry from a good one.
+        // A delta is never larger than the object it rebuilds.
+        let mut data = Vec::new();
+        ZlibDecoder::new(BufReader::new(f).take(zlen))
+            .take(raw_len.saturating_add(1))
+            .read_to_end(&mut data)
+            .context("inflate packed object")?;
+
+        let raw = match base {
+            None => data,
+            Some(base) => {
+                if data.len() as u64 > raw_len {
+                    return Err(anyhow::anyhow!("delta for {} is larger than the object", oid));
+                }
+                let base_raw = self
+                    .read_raw_at_depth(&base, depth + 1)?
+                    .ok_or_else(|| anyhow::anyhow!("delta base {} missing from pack", base))?;
+                apply_delta(&base_raw, &data)?
+            }
+        };
+        if raw.len() as u64 != raw_len {
+            return Err(anyhow::anyhow!("packed object {} has wrong size", oid));
+        }
+        Ok(Some(raw))
+    }
+
+    /// Opens the `kind\0body` bytes of an object stored in this pack for
+    /// reading. Whole entries are inflated as they are read; deltas have to
+    /// be resolved against their base first and are returned from memory.
+    pub fn open_raw(&self, oid: &Oid) -> Result<Option<Box<dyn Read>>> {
+        let offset = match self.find(oid) {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+
+        let mut f = File::open(&self.pack_path)
+            .with_context(|| format!("open pack {}", self.pack_path.display()))?;
+        let body_end = f.metadata()?.len().saturating_sub(32);
+        f.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 1 + 8 + 8];
+        f.read_exact(&mut header)?;
+        if header[0] != ENTRY_FULL {
+            return Ok(self
+                .read_raw(oid)?
+                .map(|raw| Box::new(Cursor::new(raw)) as Box<dyn Read>));
+        }
+
+        let raw_len = read_u64(&header[1..9]);
+        let zlen = read_u64(&header[9..17]);
+        if zlen > body_end.saturating_sub(f.stream_position()?) {
+            return Err(anyhow::anyhow!("pack entry for {} runs past the end of the pack", oid));
+        }
+        let compressed = BufReader::new(f).take(zlen);
+        Ok(Some(Box::new(ZlibDecoder::new(compressed).take(raw_len))))
+    }
+}
+
+/// Loads every pack index under `dir`, skipping the directory if it does not exist.
+pub fn load_packs(dir: &Path) -> Result<Vec<PackIndex>> {
+    let mut packs = Vec::new();
+    if !dir.exists() {
+        return Ok(packs);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("idx") {
+            packs.push(PackIndex::load(&path)?);
+        }
+    }
+    Ok(packs)
+}
+
+/// Writes the objects named by `oids`, read from `store`, as a new pack and
+/// index in `dir`. Returns the path of the `.pack` file.
+///
+/// Objects are streamed one at a time into a temporary file, so the pack
+/// never has to fit in memory. `bases` maps an object to the object it
+/// should be stored as a delta against. The caller keeps chains acyclic and
+/// within `MAX_DELTA_DEPTH`; a delta is only used when its base is in the
+/// pack, both fit within `MAX_DELTA_SOURCE`, and it is smaller than the
+/// object itself.
+pub fn write_pack(dir: &Path, store: &dyn ObjectStore, oids: &[Oid], bases: &HashMap<Oid, Oid>) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let mut sorted = oids.to_vec();
+    sorted.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    sorted.dedup();
+    let members: HashSet<Oid> = sorted.iter().copied().collect();
+
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    let mut out = BufWriter::new(tmp.as_file());
+    out.write_all(PACK_MAGIC)?;
+    out.write_all(&PACK_VERSION.to_be_bytes())?;
+    out.write_all(&(sorted.len() as u32).to_be_bytes())?;
+
+    let mut offsets = Vec::with_capacity(sorted.len());
+    for oid in &sorted {
+        offsets.push((*oid, out.stream_position()?));
+
+        let delta = match bases.get(oid).filter(|b| members.contains(b)) {
+            Some(base) => delta_against(store, oid, base)?,
+            None => None,
+        };
+        match delta {
+            Some((base, raw_len, d)) => {
+                write_entry(&mut out, ENTRY_DELTA, Some(&base), Some(raw_len), &mut &d[..])?;
+            }
+            None => {
+                let mut reader = store
+                    .open_raw(oid)?
+                    .ok_or_else(|| anyhow::anyhow!("object vanished while packing: {}", oid))?;
+                write_entry(&mut out, ENTRY_FULL, None, None, &mut reader)?;
+            }
+        }
+    }
+    out.flush()?;
+    drop(out);
+
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(tmp.path())?, &mut hasher)?;
+    let pack_sum = *hasher.finalize().as_bytes();
+    tmp.as_file().write_all(&pack_sum)?;
+    tmp.as_file().sync_all()?;
+
+    let mut idx = Vec::new();
+    idx.extend_from_slice(IDX_MAGIC);
+    idx.extend_from_slice(&PACK_VERSION.to_be_bytes());
+    idx.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+    for (oid, offset) in &offsets {
+        idx.extend_from_slice(oid.as_bytes());
+        idx.extend_from_slice(&offset.to_be_bytes());
+    }
+    idx.extend_from_slice(&pack_sum);
+    let idx_sum = *blake3::hash(&idx).as_bytes();
+    idx.extend_from_slice(&idx_sum);
+
+    let name = format!("pack-{}", hex::encode(pack_sum));
+    let pack_path = dir.join(format!("{}.pack", name));
+    let idx_path = dir.join(format!("{}.idx", name));
+
+    // The index is what makes a pack visible, so it is renamed into place last.
+    tmp.persist(&pack_path)?;
+    write_atomic(&idx_path, &idx)?;
+    Ok(pack_path)
+}
+
+/// Builds a delta of `oid` against `base` when both are small enough to
+/// hold in memory and the delta saves space. Returns the base, the size of
+/// the object and the delta.
+fn delta_against(store: &dyn ObjectStore, oid: &Oid, base: &Oid) -> Result<Option<(Oid, u64, Vec<u8>)>> {
+    let (Some(raw), Some(base_raw)) = (read_bounded(store, oid)?, read_bounded(store, base)?) else {
+        return Ok(None);
+    };
+    let d = create_delta(&base_raw, &raw);
+    Ok((d.len() < raw.len()).then_some((*base, raw.len() as u64, d)))
+}
+
+fn read_bounded(store: &dyn ObjectStore, oid: &Oid) -> Result<Option<Vec<u8>>> {
+    let Some(reader) = store.open_raw(oid)? else {
+        return Ok(None);
+    };
+    let mut buf = Vec::new();
+    reader.take(MAX_DELTA_SOURCE + 1).read_to_end(&mut buf)?;
+    Ok((buf.len() as u64 <= MAX_DELTA_SOURCE).then_some(buf))
+}
+
+/// Writes one entry, compressing `data` as it is read. The length fields are
+/// written as placeholders and filled in once the data has been streamed.
+/// `raw_len` overrides the recorded size, for deltas whose data is not the object itself.
+fn write_entry<W: Write + Seek>(
+    out: &mut W,
+    kind: u8,
+    base: Option<&Oid>,
+    raw_len: Option<u64>,
+    data: &mut dyn Read,
+) -> Result<()> {
+    let start = out.stream_position()?;
+    out.write_all(&[kind])?;
+    out.write_all(&0u64.to_be_bytes())?;
+    if let Some(base) = base {
+        out.write_all(base.as_bytes())?;
+    }
+    out.write_all(&0u64.to_be_bytes())?;
+    let data_start = out.stream_position()?;
+
+    let mut enc = ZlibEncoder::new(&mut *out, Compression::default());
+    let copied = io::copy(data, &mut enc)?;
+    enc.finish()?;
+    let end = out.stream_position()?;
+
+    out.seek(SeekFrom::Start(start + 1))?;
+    out.write_all(&raw_len.unwrap_or(copied).to_be_bytes())?;
+    out.seek(SeekFrom::Start(data_start - 8))?;
+    out.write_all(&(end - data_start).to_be_bytes())?;
+    out.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Walks history newest-first from `heads` and pairs each older version of a
+/// path with the next newer version, so the pack can store the older blob as
+/// a delta. Recent versions stay whole and cheap to read.
+///
+/// With `wanted`, only objects in that set are paired, and the walk stops
+/// once every one of them has been seen instead of covering all of history.
+pub fn plan_deltas(store: &dyn ObjectStore, heads: &[Oid], wanted: Option<&HashSet<Oid>>) -> Result<HashMap<Oid, Oid>> {
+    let mut bases = HashMap::new();
+    let mut depth: HashMap<Oid, usize> = HashMap::new();
+    // An object that already serves as a base is never turned into a delta,
+    // which keeps chains acyclic and the recorded depths accurate.
+    let mut used_as_base = HashSet::new();
+    let mut newer: HashMap<Vec<u8>, Oid> = HashMap::new();
+    let mut unseen = wanted.cloned();
+
+    let mut queue: VecDeque<Oid> = heads.iter().copied().collect();
+    let mut visited = HashSet::new();
+
+    while let Some(cur) = queue.pop_front() {
+        if unseen.as_ref().is_some_and(|u| u.is_empty()) {
+            break;
+        }
+        if !visited.insert(cur) {
+            continue;
+        }
+        let commit = match store.get(&cur)? {
+            Some(Object::Commit(c)) => c,
+            _ => continue,
+        };
+        let mut files = Vec::new();
+        let walked = Oid::from_hex(&commit.tree).and_then(|t| objects_in_tree(store, &t, b"", &mut files));
+        if walked.is_err() {
+            continue;
+        }
+        if let Some(u) = unseen.as_mut() {
+            u.remove(&cur);
+            for (oid, _) in &files {
+                u.remove(oid);
+            }
+        }
+
+        for (oid, path) in files {
+            let Some(path) = path else {
+                continue;
+            };
+            let Some(base) = newer.insert(path, oid) else {
+                continue;
+            };
+            if wanted.is_some_and(|w| !w.contains(&oid) || !w.contains(&base)) {
+                continue;
+            }
+            if base == oid || bases.contains_key(&oid) || used_as_base.contains(&oid) {
+                continue;
+            }
+            let d = depth.get(&base).copied().unwrap_or(0) + 1;
+            if d > MAX_DELTA_DEPTH {
+                continue;
+            }
+            bases.insert(oid, base);
+            depth.insert(oid, d);
+            used_as_base.insert(base);
+        }
+
+        for p in commit.parents {
+            if let Ok(o) = Oid::from_hex(&p) {
+                queue.push_back(o);
+            }
+        }
+    }
+    Ok(bases)
+}
+
+/// Lists every object under `tree`, subtrees included, with the full
+/// `/`-separated path of each file. Subtrees are listed without a path.
+fn objects_in_tree(store: &dyn ObjectStore, tree: &Oid, prefix: &[u8], out: &mut Vec<(Oid, Option<Vec<u8>>)>) -> Result<()> {
+    let entries = match store.get(tree)? {
+        Some(Object::Tree(entries)) => entries,
+        _ => return Err(anyhow::anyhow!("Tree not found: {}", tree)),
+    };
+    out.push((*tree, None));
+    for e in entries {
+        let path = if prefix.is_empty() {
+            e.name.clone()
+        } else {
+            [prefix, b"/", &e.name].concat()
+        };
+        if e.is_dir() {
+            objects_in_tree(store, &e.oid, &path, out)?;
+        } else {
+            out.push((e.oid, Some(path)));
+        }
+    }
+    Ok(())
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut f = File::create(&tmp)?;
+    f.write_all(data)?;
+    f.sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes(b[..4].try_into().unwrap())
+}
+
+fn read_u64(b: &[u8]) -> u64 {
+    u64::from_be_bytes(b[..8].try_into().unwrap())
+}
@@ -1,112 +1,315 @@
-use super::hashing::{blob_oid, commit_oid, commit_serialize, tree_oid, tree_serialize};
-use crate::core::types::{Commit, Object, Oid, TreeEntry, objects_dir, head_file, refs_heads_dir};
+use super::hashing::{blob_oid, commit_deserialize, commit_oid, commit_serialize, finalize_oid, object_hasher, tree_deserialize, tree_oid, tree_serialize};
+use crate::core::pack::{load_packs, PackIndex};
+use crate::repo::object_store::ObjectStore;
+use crate::core::types::{Object, Oid, TreeEntry, MODE_DIR, fanout_marker, objects_dir, packs_dir, refs_heads_dir};
 use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{BufReader, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+/// Prefix of zlib-compressed loose objects. Files without it are legacy
+/// uncompressed `kind\0body` objects and are read as-is.
+const LOOSE_MAGIC: &[u8] = b"RVZ\x01";
+
+fn compress_loose(raw: &[u8]) -> Result<Vec<u8>> {
+    let mut enc = ZlibEncoder::new(LOOSE_MAGIC.to_vec(), Compression::default());
+    enc.write_all(raw)?;
+    Ok(enc.finish()?)
+}
+
+fn decompress_loose(buf: Vec<u8>) -> Result<Vec<u8>> {
+    match buf.strip_prefix(LOOSE_MAGIC) {
+        Some(compressed) => {
+            let mut raw = Vec::new();
+            ZlibDecoder::new(compressed)
+                .read_to_end(&mut raw)
+                .context("inflate loose object")?;
+            Ok(raw)
+        }
+        None => Ok(buf),
+    }
+}
+
+/// Splits a raw `kind\0body` object into its kind and body.
+pub fn split_raw_object(raw: &[u8]) -> Result<(&str, &[u8])> {
+    let pos = raw
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow::anyhow!("malformed object file"))?;
+    let kind = std::str::from_utf8(&raw[..pos]).context("object kind utf8")?;
+    Ok((kind, &raw[pos + 1..]))
+}
+
+/// Serializes an object into its OID and raw `kind\0body` bytes.
+pub fn encode_object(obj: &Object) -> (Oid, Vec<u8>) {
+    let (kind, oid, body) = match obj {
+        Object::Blob(b) => ("blob", blob_oid(b), b.clone()),
+        Object::Tree(entries) => ("tree", tree_oid(entries), tree_serialize(entries)),
+        Object::Commit(c) => ("commit", commit_oid(c), commit_serialize(c)),
+    };
+    let mut raw = Vec::with_capacity(kind.len() + 1 + body.len());
+    raw.extend_from_slice(kind.as_bytes());
+    raw.push(0u8);
+    raw.extend_from_slice(&body);
+    (oid, raw)
+}
+
+/// Parses raw `kind\0body` bytes back into an object.
+pub fn decode_object(raw: &[u8]) -> Result<Object> {
+    let (kind, body) = split_raw_object(raw)?;
+    match kind {
+        "blob" => Ok(Object::Blob(body.to_vec())),
+        "tree" => parse_tree_object(body),
+        "commit" => parse_commit_object(body),
+        other => Err(anyhow::anyhow!("unknown object kind: {}", other)),
+    }
+}
+
+fn parse_tree_object(body: &[u8]) -> Result<Object> {
+    Ok(Object::Tree(tree_deserialize(body)?))
+}
+
+fn parse_commit_object(body: &[u8]) -> Result<Object> {
+    Ok(Object::Commit(commit_deserialize(body)?))
+}
 
 pub struct FsObjectStore {
     repo: PathBuf,
+    // Pack indexes are loaded on first use; a store does not see packs written after that.
+    packs: OnceLock<Vec<PackIndex>>,
+    // Whether objects may still be stored flat, i.e. the fan-out marker was missing on first use.
+    flat_objects: OnceLock<bool>,
 }
 
 impl FsObjectStore {
     pub fn new<P: AsRef<Path>>(repo_root: P) -> Self {
         FsObjectStore {
             repo: repo_root.as_ref().to_path_buf(),
+            packs: OnceLock::new(),
+            flat_objects: OnceLock::new(),
         }
     }
 
-    fn object_path(&self, oid: &Oid) -> PathBuf {
-        super::objects_dir(&self.repo).join(oid.to_hex())
+    /// Loose objects are sharded by their first OID byte: `objects/ab/cdef...`.
+    pub fn object_path(&self, oid: &Oid) -> PathBuf {
+        let hex = oid.to_hex();
+        objects_dir(&self.repo).join(&hex[..2]).join(&hex[2..])
+    }
+
+    // Repositories created before fan-out kept every object directly in `objects/`.
+    fn legacy_object_path(&self, oid: &Oid) -> PathBuf {
+        objects_dir(&self.repo).join(oid.to_hex())
+    }
+
+    fn existing_loose_path(&self, oid: &Oid) -> Option<PathBuf> {
+        let path = self.object_path(oid);
+        if path.exists() {
+            return Some(path);
+        }
+        let flat = *self.flat_objects.get_or_init(|| !fanout_marker(&self.repo).exists());
+        Some(self.legacy_object_path(oid)).filter(|p| flat && p.exists())
     }
 
-    pub fn put(&self, obj: &Object) -> Result<Oid> {
-        match obj {
-            Object::Blob(b) => {
-                let oid = blob_oid(b);
-                self.write_object("blob", &oid, b)?;
-                Ok(oid)
+    fn packs(&self) -> Result<&[PackIndex]> {
+        if let Some(p) = self.packs.get() {
+            return Ok(p);
+        }
+        let loaded = load_packs(&packs_dir(&self.repo))?;
+        Ok(self.packs.get_or_init(|| loaded))
+    }
+
+    /// Lists the OIDs of all loose (unpacked) objects.
+    pub fn loose_oids(&self) -> Result<Vec<Oid>> {
+        let mut out = Vec::new();
+        let dir = objects_dir(&self.repo);
+        if !dir.exists() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_file() {
+                if let Ok(oid) = Oid::from_hex(&name) {
+                    out.push(oid);
+                }
+                continue;
             }
-            Object::Tree(entries) => {
-                let oid = tree_oid(entries);
-                let data = tree_serialize(entries);
-                self.write_object("tree", &oid, &data)?;
-                Ok(oid)
+            if name.len() != 2 || hex::decode(&name).is_err() {
+                continue;
             }
-            Object::Commit(c) => {
-                let oid = commit_oid(c);
-                let data = commit_serialize(c);
-                self.write_object("commit", &oid, &data)?;
-                Ok(oid)
+            for sub in fs::read_dir(entry.path())? {
+                let sub = sub?;
+                let full = format!("{}{}", name, sub.file_name().to_string_lossy());
+                if let Ok(oid) = Oid::from_hex(&full) {
+                    out.push(oid);
+                }
             }
         }
+        Ok(out)
     }
 
-    fn write_object(&self, kind: &str, oid: &Oid, data: &[u8]) -> Result<()> {
-        let path = self.object_path(oid);
-        if !path.exists() {
-            fs::create_dir_all(path.parent().unwrap())?;
-            let mut f = File::create(&path)?;
-            f.write_all(kind.as_bytes())?;
-            f.write_all(&[0u8])?;
-            f.write_all(data)?;
-            f.sync_all()?;
+    /// Lists the OIDs of all objects held in packs.
+    pub fn packed_oids(&self) -> Result<Vec<Oid>> {
+        Ok(self.packs()?.iter().flat_map(|p| p.oids().copied()).collect())
+    }
+
+    /// Modification time of a loose object's file, if it is stored loose.
+    pub fn loose_modified(&self, oid: &Oid) -> Result<Option<std::time::SystemTime>> {
+        match self.existing_loose_path(oid) {
+            Some(path) => Ok(Some(fs::metadata(path)?.modified()?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_loose(&self, oid: &Oid) -> Result<()> {
+        if let Some(path) = self.existing_loose_path(oid) {
+            fs::remove_file(path)?;
         }
         Ok(())
     }
+}
 
-    pub fn get(&self, oid: &Oid) -> Result<Option<Object>> {
-        let path = self.object_path(oid);
-        if !path.exists() {
-            return Ok(None);
+impl ObjectStore for FsObjectStore {
+    fn write_raw(&self, oid: &Oid, raw: &[u8]) -> Result<()> {
+        if !self.contains(oid)? {
+            let path = self.object_path(oid);
+            // Written beside its final name and renamed into place, so a
+            // reader never sees a partly written object.
+            let dir = path.parent().unwrap();
+            fs::create_dir_all(dir)?;
+            let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+            tmp.write_all(&compress_loose(raw)?)?;
+            tmp.as_file().sync_all()?;
+            tmp.persist(&path)?;
         }
+        Ok(())
+    }
+
+    /// Returns the uncompressed `kind\0body` bytes of an object, whatever its on-disk encoding.
+    fn get_raw(&self, oid: &Oid) -> Result<Option<Vec<u8>>> {
+        let path = match self.existing_loose_path(oid) {
+            Some(p) => p,
+            None => {
+                for pack in self.packs()? {
+                    if let Some(raw) = pack.read_raw(oid)? {
+                        return Ok(Some(raw));
+                    }
+                }
+                return Ok(None);
+            }
+        };
 
         let mut buf = Vec::new();
         let mut f = File::open(path)?;
         f.read_to_end(&mut buf)?;
+        Ok(Some(decompress_loose(buf)?))
+    }
+
+    /// Hashes and compresses the blob in fixed-size chunks into a temporary
+    /// file, then moves it into place once the OID is known.
+    fn put_blob_stream(&self, reader: &mut dyn Read) -> Result<Oid> {
+        let dir = objects_dir(&self.repo);
+        fs::create_dir_all(&dir)?;
+        let tmp = tempfile::NamedTempFile::new_in(&dir)?;
+
+        let mut hasher = object_hasher("blob");
+        tmp.as_file().write_all(LOOSE_MAGIC)?;
+        let mut enc = ZlibEncoder::new(tmp.as_file(), Compression::default());
+        enc.write_all(b"blob\0")?;
+
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            enc.write_all(&buf[..n])?;
+        }
+        enc.finish()?;
+
+        let oid = finalize_oid(&hasher);
+        if !self.contains(&oid)? {
+            tmp.as_file().sync_all()?;
+            let path = self.object_path(&oid);
+            fs::create_dir_all(path.parent().unwrap())?;
+            tmp.persist(path)?;
+        }
+        Ok(oid)
+    }
 
-        if let Some(pos) = buf.iter().position(|&b| b == 0) {
-            let kind = &buf[..pos];
-            let body = &buf[pos + 1..];
-            match std::str::from_utf8(kind).unwrap_or("") {
-                "blob" => Ok(Some(Object::Blob(body.to_vec()))),
-                "tree" => self.parse_tree_object(body),
-                "commit" => self.parse_commit_object(body),
-                other => Err(anyhow::anyhow!("unknown object kind: {}", other)),
+    fn open_raw(&self, oid: &Oid) -> Result<Option<Box<dyn Read + '_>>> {
+        let path = match self.existing_loose_path(oid) {
+            Some(p) => p,
+            None => {
+                for pack in self.packs()? {
+                    if let Some(reader) = pack.open_raw(oid)? {
+                        return Ok(Some(reader));
+                    }
+                }
+                return Ok(None);
             }
+        };
+
+        let mut f = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; LOOSE_MAGIC.len()];
+        f.read_exact(&mut magic)?;
+        if magic == LOOSE_MAGIC {
+            Ok(Some(Box::new(ZlibDecoder::new(f))))
         } else {
-            Err(anyhow::anyhow!("malformed object file"))
+            Ok(Some(Box::new(Cursor::new(magic).chain(f))))
         }
     }
 
-    fn parse_tree_object(&self, body: &[u8]) -> Result<Option<Object>> {
-        let s = std::str::from_utf8(body).context("tree body utf8")?;
-        let mut entries = Vec::new();
+    fn open_blob(&self, oid: &Oid) -> Result<Option<Box<dyn Read + '_>>> {
+        let mut reader = match self.open_raw(oid)? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
 
-        for line in s.lines() {
-            let parts: Vec<&str> = line.split(' ').collect();
-            if parts.len() >= 3 {
-                let mode: u32 = parts[0].parse().unwrap_or(0);
-                let name = parts[1].to_string();
-                let oid_hex = parts[2];
-                let oid = Oid::from_hex(oid_hex)?;
-                entries.push(TreeEntry { mode, name, oid });
+        let mut kind = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            reader.read_exact(&mut byte).context("read object header")?;
+            if byte[0] == 0 {
+                break;
             }
+            kind.push(byte[0]);
+            if kind.len() > 16 {
+                return Err(anyhow::anyhow!("malformed object file"));
+            }
+        }
+        if kind != b"blob" {
+            return Err(anyhow::anyhow!("object {} is not a blob", oid));
         }
+        Ok(Some(reader))
+    }
 
-        Ok(Some(Object::Tree(entries)))
+    fn contains(&self, oid: &Oid) -> Result<bool> {
+        if self.existing_loose_path(oid).is_some() {
+            return Ok(true);
+        }
+        Ok(self.packs()?.iter().any(|p| p.contains(oid)))
     }
 
-    fn parse_commit_object(&self, body: &[u8]) -> Result<Option<Object>> {
-        let c: Commit = serde_json::from_slice(body)?;
-        Ok(Some(Object::Commit(c)))
+    fn list_oids(&self) -> Result<Vec<Oid>> {
+        let mut seen = HashSet::new();
+        let mut out = self.loose_oids()?;
+        out.extend(self.packed_oids()?);
+        out.retain(|o| seen.insert(*o));
+        Ok(out)
     }
 }
+
 pub fn write_tree<P: AsRef<Path>>(
    _repo: P,
     index: &crate::index::Index,
-    store: &FsObjectStore,
+    store: &dyn ObjectStore,
 ) -> anyhow::Result<String> {
     let mut entries = Vec::new();
     
@@ -119,13 +322,134 @@ pub fn write_tree<P: AsRef<Path>>(
         });
     }
     
-    let oid = store.put(&Object::Tree(entries))?;
+    let oid = write_tree_from_paths(store, &entries)?;
     Ok(oid.to_hex())
 }
 
+enum TreeNode {
+    File(u32, Oid),
+    Dir(BTreeMap<Vec<u8>, TreeNode>),
+}
+
+/// Builds nested tree objects (one per directory) from entries whose names
+/// are full `/`-separated paths, writing them bottom-up. Returns the root tree OID.
+pub fn write_tree_from_paths(store: &dyn ObjectStore, files: &[TreeEntry]) -> Result<Oid> {
+    let mut root = BTreeMap::new();
+
+    for f in files {
+        let mut parts: Vec<&[u8]> = f.name.split(|&b| b == b'/').filter(|p| !p.is_empty()).collect();
+        let leaf = match parts.pop() {
+            Some(l) => l,
+            None => continue,
+        };
+
+        let mut dir = &mut root;
+        for part in parts {
+            let node = dir
+                .entry(part.to_vec())
+                .or_insert_with(|| TreeNode::Dir(BTreeMap::new()));
+            dir = match node {
+                TreeNode::Dir(children) => children,
+                TreeNode::File(..) => {
+                    return Err(anyhow::anyhow!(
+                        "path conflict: {} is both a file and a directory",
+                        String::from_utf8_lossy(part)
+                    ));
+                }
+            };
+        }
+        dir.insert(leaf.to_vec(), TreeNode::File(f.mode, f.oid));
+    }
+
+    write_tree_node(store, &root)
+}
+
+fn write_tree_node(store: &dyn ObjectStore, children: &BTreeMap<Vec<u8>, TreeNode>) -> Result<Oid> {
+    let mut entries = Vec::new();
+    for (name, node) in children {
+        let (mode, oid) = match node {
+            TreeNode::File(mode, oid) => (*mode, *oid),
+            TreeNode::Dir(sub) => (MODE_DIR, write_tree_node(store, sub)?),
+        };
+        entries.push(TreeEntry { mode, name: name.clone(), oid });
+    }
+    store.put(&Object::Tree(entries))
+}
+
+/// Recursively walks a tree and returns every file entry with its name set to
+/// the full `/`-separated path from the root.
+pub fn flatten_tree(store: &dyn ObjectStore, tree_oid: &Oid) -> Result<Vec<TreeEntry>> {
+    let mut out = Vec::new();
+    flatten_tree_into(store, tree_oid, b"", &mut out)?;
+    Ok(out)
+}
+
+fn flatten_tree_into(store: &dyn ObjectStore, tree_oid: &Oid, prefix: &[u8], out: &mut Vec<TreeEntry>) -> Result<()> {
+    let entries = match store.get(tree_oid)? {
+        Some(Object::Tree(entries)) => entries,
+        _ => return Err(anyhow::anyhow!("Tree not found: {}", tree_oid)),
+    };
+
+    for e in entries {
+        let path = if prefix.is_empty() {
+            e.name.clone()
+        } else {
+            [prefix, b"/", &e.name].concat()
+        };
+        if e.is_dir() {
+            flatten_tree_into(store, &e.oid, &path, out)?;
+        } else {
+            out.push(TreeEntry { mode: e.mode, name: path, oid: e.oid });
+        }
+    }
+    Ok(())
+}
+
+/// Moves loose objects stored directly in `objects/` into their fan-out
+/// subdirectories. Returns how many were moved. The scan runs once: afterwards
+/// `fanout_marker` is written and later calls return immediately.
+pub fn migrate_loose_fanout<P: AsRef<Path>>(repo: P) -> anyhow::Result<usize> {
+    let dir = objects_dir(&repo);
+    let marker = fanout_marker(&repo);
+    if !dir.exists() || marker.exists() {
+        return Ok(0);
+    }
+
+    let store = FsObjectStore::new(&repo);
+    let mut moved = 0;
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let oid = match Oid::from_hex(&entry.file_name().to_string_lossy()) {
+            Ok(o) => o,
+            Err(_) => continue,
+        };
+        let target = store.object_path(&oid);
+        fs::create_dir_all(target.parent().unwrap())?;
+        if target.exists() {
+            fs::remove_file(entry.path())?;
+        } else {
+            fs::rename(entry.path(), target)?;
+        }
+        moved += 1;
+    }
+    write_fanout_marker(repo.as_ref())?;
+    Ok(moved)
+}
+
+fn write_fanout_marker(repo: &Path) -> anyhow::Result<()> {
+    let marker = fanout_marker(repo);
+    fs::create_dir_all(marker.parent().unwrap())?;
+    fs::write(marker, b"")?;
+    Ok(())
+}
+
 pub fn init<P: AsRef<Path>>(repo: P) -> anyhow::Result<()> {
     fs::create_dir_all(objects_dir(&repo))?;
     fs::create_dir_all(refs_heads_dir(&repo))?;
-    fs::write(head_file(&repo), "")?;
+    write_fanout_marker(repo.as_ref())?;
+    crate::repo::refs::set_head_branch(repo.as_ref(), crate::repo::refs::DEFAULT_BRANCH)?;
     Ok(())
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, FixedOffset, Local};
 use std::fmt;
 
 /// 32-byte OID (blake3)
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Oid([u8; 32]);
 
 impl Oid {
@@ -14,6 +14,10 @@ impl Oid {
         Oid([0u8; 32])
     }
     
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+    
     pub fn to_hex(&self) -> String {
         hex::encode(self.0)
     }
@@ -50,20 +54,122 @@ pub enum Object {
     Commit(Commit),
 }
 
+/// Mode of a regular file entry.
+pub const MODE_FILE: u32 = 0o100644;
+/// Mode of an executable file entry.
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+/// Mode of a symbolic link entry; the blob holds the link target.
+pub const MODE_SYMLINK: u32 = 0o120000;
+/// Mode of a subtree (directory) entry.
+pub const MODE_DIR: u32 = 0o040000;
+
 #[derive(Debug, Clone)]
 pub struct TreeEntry {
     pub mode: u32,
-    pub name: String,
+    /// Raw file name bytes; not necessarily UTF-8.
+    pub name: Vec<u8>,
     pub oid: Oid,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl TreeEntry {
+    pub fn is_dir(&self) -> bool {
+        self.mode == MODE_DIR
+    }
+}
+
+/// Who made a change and when, in their local timezone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the Unix epoch.
+    pub time: i64,
+    /// Offset from UTC in minutes, e.g. `330` for +05:30.
+    pub offset_minutes: i32,
+}
+
+impl Signature {
+    pub fn new(name: &str, email: &str, time: i64, offset_minutes: i32) -> Self {
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            offset_minutes,
+        }
+    }
+
+    /// Signs as `name <email>` at the current time in the local timezone.
+    pub fn now(name: &str, email: &str) -> Self {
+        let now = Local::now();
+        Signature::new(name, email, now.timestamp(), now.offset().local_minus_utc() / 60)
+    }
+
+    /// Splits a `Name <email>` identity; the email is empty if there are no brackets.
+    pub fn from_ident(ident: &str, time: i64, offset_minutes: i32) -> Self {
+        let (name, email) = match (ident.rfind('<'), ident.rfind('>')) {
+            (Some(lt), Some(gt)) if lt < gt => (ident[..lt].trim(), &ident[lt + 1..gt]),
+            _ => (ident.trim(), ""),
+        };
+        Signature::new(name, email, time, offset_minutes)
+    }
+
+    /// Parses the `Name <email> <seconds> <+hhmm>` form written by `Display`.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let bad = || anyhow::anyhow!("malformed signature: {}", s);
+        let mut parts = s.rsplitn(3, ' ');
+        let offset = parts.next().ok_or_else(bad)?;
+        let time = parts.next().ok_or_else(bad)?.parse::<i64>().map_err(|_| bad())?;
+        let ident = parts.next().ok_or_else(bad)?;
+
+        let (sign, digits) = match offset.split_at_checked(1) {
+            Some(("+", d)) => (1, d),
+            Some(("-", d)) => (-1, d),
+            _ => return Err(bad()),
+        };
+        if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(bad());
+        }
+        let hours: i32 = digits[..2].parse()?;
+        let minutes: i32 = digits[2..].parse()?;
+        Ok(Signature::from_ident(ident, time, sign * (hours * 60 + minutes)))
+    }
+
+    /// The `Name <email>` part, without the timestamp.
+    pub fn ident(&self) -> String {
+        format!("{} <{}>", self.name, self.email)
+    }
+
+    /// The signature time in its own timezone.
+    pub fn datetime(&self) -> DateTime<FixedOffset> {
+        let tz = FixedOffset::east_opt(self.offset_minutes * 60)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        DateTime::from_timestamp(self.time, 0)
+            .unwrap_or_default()
+            .with_timezone(&tz)
+    }
+
+    fn offset_string(&self) -> String {
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        let abs = self.offset_minutes.abs();
+        format!("{}{:02}{:02}", sign, abs / 60, abs % 60)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.ident(), self.time, self.offset_string())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Commit {
     pub tree: String,
     pub parents: Vec<String>,
-    pub author: String,
+    /// Who originally wrote the change.
+    pub author: Signature,
+    /// Who recorded this commit; differs from the author for merges and replays.
+    pub committer: Signature,
     pub message: String,
-    pub timestamp: i64,
 }
 pub fn repo_dir<P: AsRef<std::path::Path>>(p: P) -> std::path::PathBuf {
     p.as_ref().join(".rvc")
@@ -73,6 +179,15 @@ pub fn objects_dir<P: AsRef<std::path::Path>>(p: P) -> std::path::PathBuf {
     repo_dir(p).join("objects")
 }
 
+/// Present once loose objects are known to live in fan-out directories.
+pub fn fanout_marker<P: AsRef<std::path::Path>>(p: P) -> std::path::PathBuf {
+    repo_dir(p).join("info").join("fanout")
+}
+
+pub fn packs_dir<P: AsRef<std::path::Path>>(p: P) -> std::path::PathBuf {
+    objects_dir(p).join("pack")
+}
+
 pub fn refs_heads_dir<P: AsRef<std::path::Path>>(p: P) -> std::path::PathBuf {
     repo_dir(p).join("refs").join("heads")
 }
@@ -1,47 +1,162 @@
-use crate::core::{FsObjectStore, Object};
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::core::Oid;
+use crate::repo::object_store::ObjectStore;
+use crate::repo::worktree;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::{Path};
 use std::time::{UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+// Index layout (entries sorted by path):
+//   "RVCI" | version u32 | count u32 | entries... | extensions... | blake3(everything before)
+// Entry:
+//   ctime u64 | ctime_nsec u32 | mtime u64 | mtime_nsec u32 | ino u64 | size u64 | mode u32 | oid [32] | path_len u32 | path
+// Extension:
+//   signature [4] | len u32 | data
+
+const INDEX_MAGIC: &[u8; 4] = b"RVCI";
+const INDEX_VERSION: u32 = 1;
+
+const ENTRY_FIXED_LEN: usize = 8 + 4 + 8 + 4 + 8 + 8 + 4 + 32 + 4;
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct IndexEntry {
-    pub path: String,
+    /// `/`-separated path from the repository root, as raw bytes.
+    #[serde(deserialize_with = "path_from_json")]
+    pub path: Vec<u8>,
     pub oid: String,
     pub mode: u32,
     pub mtime: u64,
     pub size: u64,
+    // Finer stat data for the status fast path; absent in older index files.
+    #[serde(default)]
+    pub mtime_nsec: u32,
+    #[serde(default)]
+    pub ctime: u64,
+    #[serde(default)]
+    pub ctime_nsec: u32,
+    #[serde(default)]
+    pub ino: u64,
+}
+
+/// The stat fields cached per entry, as read from file metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatData {
+    pub mtime: u64,
+    pub mtime_nsec: u32,
+    pub ctime: u64,
+    pub ctime_nsec: u32,
+    pub ino: u64,
+    pub size: u64,
+}
+
+impl StatData {
+    pub fn from_metadata(meta: &fs::Metadata) -> Result<Self> {
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH)?;
+        #[cfg(unix)]
+        let (ctime, ctime_nsec, ino) = {
+            use std::os::unix::fs::MetadataExt;
+            (meta.ctime().max(0) as u64, meta.ctime_nsec().max(0) as u32, meta.ino())
+        };
+        #[cfg(not(unix))]
+        let (ctime, ctime_nsec, ino) = (0, 0, 0);
+
+        Ok(StatData {
+            mtime: modified.as_secs(),
+            mtime_nsec: modified.subsec_nanos(),
+            ctime,
+            ctime_nsec,
+            ino,
+            size: meta.len(),
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+impl IndexEntry {
+    pub fn stat(&self) -> StatData {
+        StatData {
+            mtime: self.mtime,
+            mtime_nsec: self.mtime_nsec,
+            ctime: self.ctime,
+            ctime_nsec: self.ctime_nsec,
+            ino: self.ino,
+            size: self.size,
+        }
+    }
+
+    pub fn set_stat(&mut self, stat: &StatData) {
+        self.mtime = stat.mtime;
+        self.mtime_nsec = stat.mtime_nsec;
+        self.ctime = stat.ctime;
+        self.ctime_nsec = stat.ctime_nsec;
+        self.ino = stat.ino;
+        self.size = stat.size;
+    }
+
+    fn new(path: Vec<u8>, oid: String, mode: u32, stat: &StatData) -> Self {
+        let mut entry = IndexEntry { path, oid, mode, mtime: 0, size: 0, mtime_nsec: 0, ctime: 0, ctime_nsec: 0, ino: 0 };
+        entry.set_stat(stat);
+        entry
+    }
+}
+
+/// An optional section stored after the entries, identified by a four-byte
+/// signature.
+///
+/// As in git, a signature starting with an uppercase letter marks a section
+/// that older readers may skip; such sections are kept as-is when the index is
+/// rewritten. Any other unknown section makes the index unreadable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexExtension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl IndexExtension {
+    pub fn is_optional(&self) -> bool {
+        self.signature[0].is_ascii_uppercase()
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
 pub struct Index {
-    pub entries: HashMap<String, IndexEntry>,
+    #[serde(deserialize_with = "entries_from_json")]
+    pub entries: BTreeMap<Vec<u8>, IndexEntry>,
+    #[serde(skip)]
+    pub extensions: Vec<IndexExtension>,
+    /// Modification time of the index file when it was loaded or saved, as
+    /// `(seconds, nanoseconds)`.
+    #[serde(skip)]
+    timestamp: Option<(u64, u32)>,
 }
 
 impl Index {
     pub fn load<P: AsRef<Path>>(repo: P) -> Result<Self> {
         let p = crate::core::index_file(repo);
-        if p.exists() {
-            let s = fs::read_to_string(p)?;
-            let idx: Index = serde_json::from_str(&s)?;
-            Ok(idx)
-        } else {
-            Ok(Index::default())
+        if !p.exists() {
+            return Ok(Index::default());
         }
+        let buf = fs::read(&p).with_context(|| format!("read index {}", p.display()))?;
+        let mut idx = if is_legacy_json(&buf) {
+            serde_json::from_slice(&buf)?
+        } else {
+            decode(&buf).with_context(|| format!("invalid index {}", p.display()))?
+        };
+        idx.timestamp = file_timestamp(&p);
+        Ok(idx)
     }
 
-    pub fn save<P: AsRef<Path>>(&self, repo: P) -> Result<()> {
+    pub fn save<P: AsRef<Path>>(&mut self, repo: P) -> Result<()> {
         let p = crate::core::index_file(repo);
         fs::create_dir_all(p.parent().unwrap())?;
         let tmp = p.with_extension("tmp");
         let mut f = File::create(&tmp)?;
-        let s = serde_json::to_vec(self)?;
-        f.write_all(&s)?;
+        f.write_all(&self.encode()?)?;
         f.sync_all()?;
-        fs::rename(tmp, p)?;
+        fs::rename(tmp, &p)?;
+        self.timestamp = file_timestamp(&p);
         Ok(())
     }
 
@@ -49,29 +164,181 @@ impl Index {
         &mut self,
         repo: P,
         relpath: &Path,
-        store: &FsObjectStore,
+        store: &dyn ObjectStore,
     ) -> Result<String> {
         let abs = repo.as_ref().join(relpath);
-        let mut f = File::open(&abs)?;
-        let mut buf = Vec::new();
-        f.read_to_end(&mut buf)?;
+        let meta = fs::symlink_metadata(&abs)?;
+        let oid = store.put_blob_stream(&mut worktree::open_content(&abs)?)?;
+        let stat = StatData::from_metadata(&meta)?;
 
-        let oid = store.put(&Object::Blob(buf))?;
-        let meta = fs::metadata(&abs)?;
-
-        let entry = IndexEntry {
-            path: relpath.to_string_lossy().to_string(),
-            oid: oid.to_hex(),
-            mode: 0o100644,
-            mtime: meta.modified()?.duration_since(UNIX_EPOCH)?.as_secs(),
-            size: meta.len(),
-        };
+        let entry = IndexEntry::new(worktree::to_repo_path(relpath), oid.to_hex(), worktree::file_mode(&meta), &stat);
 
         self.entries.insert(entry.path.clone(), entry.clone());
         Ok(entry.oid)
     }
 
+    /// Records a file just written to the working directory as `oid`, taking
+    /// its stat data from disk.
+    pub fn record_checkout<P: AsRef<Path>>(&mut self, repo: P, relpath: &[u8], oid: &Oid, mode: u32) -> Result<()> {
+        let abs = repo.as_ref().join(worktree::from_repo_path(relpath));
+        let stat = StatData::from_metadata(&fs::symlink_metadata(abs)?)?;
+        let entry = IndexEntry::new(relpath.to_vec(), oid.to_hex(), mode, &stat);
+        self.entries.insert(entry.path.clone(), entry);
+        Ok(())
+    }
+
+    /// Points the entry for `path` at a committed blob, or drops it for
+    /// `None`. Cached stat data is kept when the content and mode are
+    /// unchanged; otherwise it is cleared so the file gets rehashed.
+    pub fn reset_entry(&mut self, path: &[u8], target: Option<(Oid, u32)>) {
+        let Some((oid, mode)) = target else {
+            self.entries.remove(path);
+            return;
+        };
+        let hex = oid.to_hex();
+        if self.entries.get(path).is_some_and(|e| e.oid == hex && e.mode == mode) {
+            return;
+        }
+        let stat = StatData { mtime: 0, mtime_nsec: 0, ctime: 0, ctime_nsec: 0, ino: 0, size: 0 };
+        self.entries.insert(path.to_vec(), IndexEntry::new(path.to_vec(), hex, mode, &stat));
+    }
+
+    /// Whether `entry` can be trusted to match a file with stat data `stat`
+    /// without rehashing it.
+    ///
+    /// An entry written in the same instant as the index file itself is
+    /// "racily clean": the file may have changed again within the timestamp
+    /// granularity after it was hashed, so it is always rehashed.
+    pub fn stat_matches(&self, entry: &IndexEntry, stat: &StatData) -> bool {
+        if entry.stat() != *stat {
+            return false;
+        }
+        match self.timestamp {
+            Some(index_time) => (entry.mtime, entry.mtime_nsec) < index_time,
+            None => false,
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.entries.is_empty()
     }
-}
\ No newline at end of file
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(INDEX_MAGIC);
+        buf.extend_from_slice(&INDEX_VERSION.to_be_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in self.entries.values() {
+            buf.extend_from_slice(&entry.ctime.to_be_bytes());
+            buf.extend_from_slice(&entry.ctime_nsec.to_be_bytes());
+            buf.extend_from_slice(&entry.mtime.to_be_bytes());
+            buf.extend_from_slice(&entry.mtime_nsec.to_be_bytes());
+            buf.extend_from_slice(&entry.ino.to_be_bytes());
+            buf.extend_from_slice(&entry.size.to_be_bytes());
+            buf.extend_from_slice(&entry.mode.to_be_bytes());
+            buf.extend_from_slice(Oid::from_hex(&entry.oid)?.as_bytes());
+            buf.extend_from_slice(&(entry.path.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&entry.path);
+        }
+        for ext in &self.extensions {
+            buf.extend_from_slice(&ext.signature);
+            buf.extend_from_slice(&(ext.data.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&ext.data);
+        }
+        let checksum = *blake3::hash(&buf).as_bytes();
+        buf.extend_from_slice(&checksum);
+        Ok(buf)
+    }
+}
+
+fn decode(buf: &[u8]) -> Result<Index> {
+    if buf.len() < 12 + 32 || &buf[..4] != INDEX_MAGIC {
+        return Err(anyhow::anyhow!("not an index file"));
+    }
+    let (body, checksum) = buf.split_at(buf.len() - 32);
+    if blake3::hash(body).as_bytes() != checksum {
+        return Err(anyhow::anyhow!("index checksum mismatch"));
+    }
+    let version = read_u32(&body[4..8]);
+    if version != INDEX_VERSION {
+        return Err(anyhow::anyhow!("unsupported index version {}", version));
+    }
+    let count = read_u32(&body[8..12]) as usize;
+
+    let mut idx = Index::default();
+    let mut pos = 12;
+    for _ in 0..count {
+        let fixed = body.get(pos..pos + ENTRY_FIXED_LEN).ok_or_else(|| anyhow::anyhow!("truncated index"))?;
+        let path_len = read_u32(&fixed[ENTRY_FIXED_LEN - 4..]) as usize;
+        let path_start = pos + ENTRY_FIXED_LEN;
+        let path = body.get(path_start..path_start + path_len).ok_or_else(|| anyhow::anyhow!("truncated index"))?;
+        let entry = IndexEntry {
+            path: path.to_vec(),
+            oid: Oid::from(&fixed[44..76]).to_hex(),
+            mode: read_u32(&fixed[40..44]),
+            ctime: read_u64(&fixed[0..8]),
+            ctime_nsec: read_u32(&fixed[8..12]),
+            mtime: read_u64(&fixed[12..20]),
+            mtime_nsec: read_u32(&fixed[20..24]),
+            ino: read_u64(&fixed[24..32]),
+            size: read_u64(&fixed[32..40]),
+        };
+        idx.entries.insert(entry.path.clone(), entry);
+        pos = path_start + path_len;
+    }
+
+    while pos < body.len() {
+        let header = body.get(pos..pos + 8).ok_or_else(|| anyhow::anyhow!("truncated index extension"))?;
+        let len = read_u32(&header[4..8]) as usize;
+        let data = body.get(pos + 8..pos + 8 + len).ok_or_else(|| anyhow::anyhow!("truncated index extension"))?;
+        let ext = IndexExtension { signature: header[..4].try_into()?, data: data.to_vec() };
+        if !ext.is_optional() {
+            return Err(anyhow::anyhow!(
+                "unsupported index extension '{}'",
+                String::from_utf8_lossy(&ext.signature)
+            ));
+        }
+        idx.extensions.push(ext);
+        pos += 8 + len;
+    }
+    Ok(idx)
+}
+
+// JSON indexes stored paths as strings, both as entry fields and as map keys.
+fn path_from_json<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<u8>, D::Error> {
+    Ok(String::deserialize(d)?.into_bytes())
+}
+
+fn entries_from_json<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<BTreeMap<Vec<u8>, IndexEntry>, D::Error> {
+    let entries = BTreeMap::<String, IndexEntry>::deserialize(d)?;
+    Ok(entries.into_iter().map(|(k, v)| (k.into_bytes(), v)).collect())
+}
+
+/// Indexes written before the binary format were a JSON object.
+fn is_legacy_json(buf: &[u8]) -> bool {
+    buf.first() == Some(&b'{')
+}
+
+/// Rewrites a JSON index from older versions in the binary format. Returns
+/// whether anything was converted.
+pub fn migrate_legacy_index(repo: &Path) -> Result<bool> {
+    let p = crate::core::index_file(repo);
+    if !p.exists() || !is_legacy_json(&fs::read(&p)?) {
+        return Ok(false);
+    }
+    Index::load(repo)?.save(repo)?;
+    Ok(true)
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes(b[..4].try_into().unwrap())
+}
+
+fn read_u64(b: &[u8]) -> u64 {
+    u64::from_be_bytes(b[..8].try_into().unwrap())
+}
+
+fn file_timestamp(path: &Path) -> Option<(u64, u32)> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((modified.as_secs(), modified.subsec_nanos()))
+}
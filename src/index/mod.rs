@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod index;
-pub use index::Index;
\ No newline at end of file
+pub use index::{migrate_legacy_index, Index, IndexExtension, StatData};
\ No newline at end of file
@@ -9,11 +9,36 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
     let cwd = std::env::current_dir()?;
 
+    if rvc::core::repo_dir(&cwd).exists() {
+        let moved = rvc::core::migrate_loose_fanout(&cwd)?;
+        if moved > 0 {
+            println!("Migrated {} loose objects into fan-out directories", moved);
+        }
+        if rvc::repo::refs::migrate_legacy_head(&cwd)? {
+            println!("Moved HEAD onto branch {}", rvc::repo::refs::DEFAULT_BRANCH);
+        }
+        if rvc::index::migrate_legacy_index(&cwd)? {
+            println!("Converted the index to the binary format");
+        }
+    }
+
     match cli.command {
         commands::Commands::Init => commands::init::execute(&cwd)?,
-        commands::Commands::Add { path } => commands::add::execute(&cwd, &path)?,
-        commands::Commands::Commit { message } => commands::commit::execute(&cwd, &message)?,
-        commands::Commands::Log => commands::log::execute(&cwd)?,
+        commands::Commands::Add { paths, all, update } => commands::add::execute(&cwd, &paths, all, update)?,
+        commands::Commands::Commit { message, file, allow_empty, amend } => {
+            let message = match file {
+                Some(path) if path.as_os_str() == "-" => Some(std::io::read_to_string(std::io::stdin())?),
+                Some(path) => Some(std::fs::read_to_string(&path)?),
+                None => message,
+            };
+            commands::commit::execute(&cwd, message.as_deref(), allow_empty, amend)?
+        }
+        commands::Commands::Rm { paths, cached, r, force } => commands::rm::execute(&cwd, &paths, cached, r, force)?,
+        commands::Commands::Mv { mut paths, force } => {
+            let dest = paths.pop().unwrap_or_default();
+            commands::mv::execute(&cwd, &paths, &dest, force)?
+        }
+        commands::Commands::Log { rev } => commands::log::execute(&cwd, rev.as_deref())?,
         commands::Commands::Status => commands::status::execute(&cwd)?, 
         commands::Commands::Diff => commands::diff::execute(&cwd)?, 
         commands::Commands::Node { port } => { 
@@ -46,7 +71,31 @@ async fn main() -> Result<()> {
                 std::process::exit(1);
             }
         },
-        commands::Commands::Checkout { hash } => commands::checkout::execute(&cwd, &hash)?,
+        commands::Commands::Checkout { rev, force } => commands::checkout::execute(&cwd, &rev, force)?,
+        commands::Commands::RevParse { rev } => commands::rev_parse::execute(&cwd, &rev)?,
+        commands::Commands::Clean { dry_run, force, ignored } => commands::clean::execute(&cwd, dry_run, force, ignored)?,
+        commands::Commands::Restore { paths, staged, source } => {
+            commands::restore::execute(&cwd, &paths, staged, source.as_deref())?
+        }
+        commands::Commands::Reset { rev, soft, mixed: _, hard } => {
+            let mode = if soft {
+                commands::reset::ResetMode::Soft
+            } else if hard {
+                commands::reset::ResetMode::Hard
+            } else {
+                commands::reset::ResetMode::Mixed
+            };
+            commands::reset::execute(&cwd, rev.as_deref().unwrap_or("HEAD"), mode)?
+        }
+        commands::Commands::CheckIgnore { paths, verbose } => commands::check_ignore::execute(&cwd, &paths, verbose)?,
+        commands::Commands::Branch { action } => commands::branch::execute(&cwd, action.as_ref())?,
+        commands::Commands::Switch { target, create, detach, force } => {
+            commands::switch::execute(&cwd, &target, create, detach, force)?
+        }
+        commands::Commands::Gc => commands::gc::execute(&cwd)?,
+        commands::Commands::Fsck => commands::fsck::execute(&cwd)?,
+        commands::Commands::Prune { dry_run, grace_days } => commands::prune::execute(&cwd, dry_run, grace_days)?,
+        commands::Commands::Config { action } => commands::config::execute(&cwd, &action)?,
     }
     Ok(())
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use libp2p::{
-    kad::{self, Record, store::MemoryStore},
+    kad::{self, store::MemoryStore},
     mdns,
     ping,
     request_response,
@@ -35,7 +35,7 @@ impl RvcBehaviour {
 
         let ping = ping::Behaviour::default();
         let req_res = RequestResponseBehaviour::with_codec(
-       RvcCodec::default(), 
+       RvcCodec::new(), 
        iter::once((RvcProtocol, ProtocolSupport::Full)), 
       request_response::Config::default(),
     );
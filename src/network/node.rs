@@ -3,17 +3,15 @@ use crate::network::{
     transport::build_transport,
     behaviour::{RvcBehaviour, RvcEvent, repo_key},
 };
-use crate::sync::messages::{SyncRequest, SyncResponse};
 use libp2p::{
-    swarm::{Swarm, SwarmEvent, dial_opts::DialOpts},
+    swarm::{Swarm, SwarmEvent},
     Multiaddr, PeerId,
 };
-use std::collections::{HashSet, HashMap};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use futures::StreamExt;
 use libp2p::mdns::Event as MdnsEvent;
 use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
-use libp2p::kad::{Record, store::MemoryStore, Event as KadEvent, QueryResult, GetRecordOk, PeerRecord};
+use libp2p::kad::{Record, Event as KadEvent, QueryResult, GetRecordOk, PeerRecord};
 
 pub async fn create_swarm(port: Option<u16>, identity_port: u16) -> Result<(Swarm<RvcBehaviour>, PeerId), Box<dyn std::error::Error>> {
     let identity = load_or_generate_identity(identity_port);
@@ -165,23 +163,20 @@ pub async fn peers_cmd(cwd: &Path, repo: &str) -> Result<(), Box<dyn std::error:
     swarm.behaviour_mut().kad.get_record(key);
 
     loop {
-        match swarm.select_next_some().await {
-            SwarmEvent::Behaviour(RvcEvent::Kad(KadEvent::OutboundQueryProgressed { result: QueryResult::GetRecord(res), .. })) => {
-                match res {
-                    Ok(GetRecordOk::FoundRecord(PeerRecord { record: Record { value, .. }, .. })) => {
-                        if let Ok(peer) = PeerId::from_bytes(&value) {
-                            println!("Found peer for repo: {}", peer);
-                        }
-                        break;
+        if let SwarmEvent::Behaviour(RvcEvent::Kad(KadEvent::OutboundQueryProgressed { result: QueryResult::GetRecord(res), .. })) = swarm.select_next_some().await {
+            match res {
+                Ok(GetRecordOk::FoundRecord(PeerRecord { record: Record { value, .. }, .. })) => {
+                    if let Ok(peer) = PeerId::from_bytes(&value) {
+                        println!("Found peer for repo: {}", peer);
                     }
-                    Err(e) => {
-                        println!("Failed to find peers: {:?}", e);
-                        break;
-                    }
-                    _ => {}
+                    break;
                 }
+                Err(e) => {
+                    println!("Failed to find peers: {:?}", e);
+                    break;
+                }
+                _ => {}
             }
-            _ => {}
         }
     }
     Ok(())
@@ -241,44 +236,41 @@ pub async fn sync_cmd(cwd: &Path, repo: &str, port: Option<u16>) -> Result<(), B
                             }
                         }
                     }
-                    SwarmEvent::Behaviour(RvcEvent::Kad(KadEvent::OutboundQueryProgressed { result: QueryResult::GetRecord(res), .. })) => {
-                        if let Ok(GetRecordOk::FoundRecord(PeerRecord { record: Record { value, .. }, .. })) = res {
-                            if let Ok(peer_id) = PeerId::from_bytes(&value) {
-                                println!("DHT Discovered peer: {}", peer_id);
-                                target_peer = Some(peer_id);
-                                
-                                if swarm.is_connected(&peer_id) {
-                                    println!("Peer {} already connected (DHT). Starting sync...", peer_id);
-                                    if let Err(e) = crate::sync::manager::sync_with_peer(peer_id, cwd, &mut swarm).await {
-                                        println!("Sync with peer {} failed: {}. Continuing discovery...", peer_id, e);
-                                    } else {
-                                        println!("Sync with peer {} successful!", peer_id);
-                                        return Ok(());
-                                    }
+                    SwarmEvent::Behaviour(RvcEvent::Kad(KadEvent::OutboundQueryProgressed {
+                        result: QueryResult::GetRecord(Ok(GetRecordOk::FoundRecord(PeerRecord { record: Record { value, .. }, .. }))),
+                        ..
+                    })) => {
+                        if let Ok(peer_id) = PeerId::from_bytes(&value) {
+                            println!("DHT Discovered peer: {}", peer_id);
+                            target_peer = Some(peer_id);
+                            
+                            if swarm.is_connected(&peer_id) {
+                                println!("Peer {} already connected (DHT). Starting sync...", peer_id);
+                                if let Err(e) = crate::sync::manager::sync_with_peer(peer_id, cwd, &mut swarm).await {
+                                    println!("Sync with peer {} failed: {}. Continuing discovery...", peer_id, e);
                                 } else {
-                                    println!("Dialing peer from DHT: {}", peer_id);
-                                    let _ = swarm.dial(peer_id);
+                                    println!("Sync with peer {} successful!", peer_id);
+                                    return Ok(());
                                 }
-                            }
-                        }
-                    }
-                    SwarmEvent::ConnectionEstablished { peer_id, .. } => {
-                        if Some(peer_id) == target_peer {
-                            println!("Connection established to {}. Starting sync...", peer_id);
-                            if let Err(e) = crate::sync::manager::sync_with_peer(peer_id, cwd, &mut swarm).await {
-                                println!("Sync with peer {} failed: {}. Continuing discovery...", peer_id, e);
                             } else {
-                                println!("Sync with peer {} successful!", peer_id);
-                                return Ok(());
+                                println!("Dialing peer from DHT: {}", peer_id);
+                                let _ = swarm.dial(peer_id);
                             }
                         }
                     }
-                    SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
-                        if Some(peer_id) == target_peer {
-                            println!("Failed to connect to {}: {:?}", peer_id, error);
-                            // Keep trying discovery
+                    SwarmEvent::ConnectionEstablished { peer_id, .. } if Some(peer_id) == target_peer => {
+                        println!("Connection established to {}. Starting sync...", peer_id);
+                        if let Err(e) = crate::sync::manager::sync_with_peer(peer_id, cwd, &mut swarm).await {
+                            println!("Sync with peer {} failed: {}. Continuing discovery...", peer_id, e);
+                        } else {
+                            println!("Sync with peer {} successful!", peer_id);
+                            return Ok(());
                         }
                     }
+                    SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } if Some(peer_id) == target_peer => {
+                        println!("Failed to connect to {}: {:?}", peer_id, error);
+                        // Keep trying discovery
+                    }
                     _ => {}
                 }
             }
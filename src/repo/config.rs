@@ -0,0 +1,160 @@
+use crate::core::Signature;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that overrides the location of the global config file.
+pub const GLOBAL_CONFIG_ENV: &str = "RVC_GLOBAL_CONFIG";
+
+/// Key/value settings stored in a git-style INI file:
+///
+/// ```text
+/// [user]
+///     name = Ada Lovelace
+///     email = ada@example.com
+/// ```
+///
+/// Keys are addressed as `section.name`.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    entries: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut cfg = Config::default();
+        if !path.exists() {
+            return Ok(cfg);
+        }
+
+        let mut section = String::new();
+        for (n, raw) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("{}:{}: expected `key = value`", path.display(), n + 1))?;
+            if section.is_empty() {
+                return Err(anyhow::anyhow!("{}:{}: key outside of a section", path.display(), n + 1));
+            }
+            cfg.entries
+                .insert(format!("{}.{}", section, key.trim()), value.trim().to_string());
+        }
+        Ok(cfg)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut sections: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        for (key, value) in &self.entries {
+            let (section, name) = split_key(key)?;
+            sections.entry(section).or_default().push((name, value));
+        }
+
+        let mut out = String::new();
+        for (section, entries) in sections {
+            out.push_str(&format!("[{}]\n", section));
+            for (name, value) in entries {
+                out.push_str(&format!("    {} = {}\n", name, value));
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        split_key(key)?;
+        if value.contains('\n') {
+            return Err(anyhow::anyhow!("config values must be a single line"));
+        }
+        self.entries.insert(key.to_string(), value.trim().to_string());
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> bool {
+        self.entries.remove(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Overlays `other` on top of this config; its values win.
+    pub fn merge(&mut self, other: Config) {
+        self.entries.extend(other.entries);
+    }
+}
+
+fn split_key(key: &str) -> Result<(&str, &str)> {
+    match key.rsplit_once('.') {
+        Some((section, name)) if !section.is_empty() && !name.is_empty() && !name.contains(['=', ' ']) => {
+            Ok((section, name))
+        }
+        _ => Err(anyhow::anyhow!("invalid config key `{}`: expected section.name", key)),
+    }
+}
+
+pub fn repo_config_path(repo: &Path) -> PathBuf {
+    crate::core::types::repo_dir(repo).join("config")
+}
+
+pub fn global_config_path() -> Option<PathBuf> {
+    if let Some(p) = std::env::var_os(GLOBAL_CONFIG_ENV) {
+        return Some(PathBuf::from(p));
+    }
+    dirs::home_dir().map(|h| h.join(".rvcconfig"))
+}
+
+/// Loads the global config with the repository config layered on top.
+pub fn load_config(repo: &Path) -> Result<Config> {
+    let mut cfg = match global_config_path() {
+        Some(p) => Config::load(&p)?,
+        None => Config::default(),
+    };
+    cfg.merge(Config::load(&repo_config_path(repo))?);
+    Ok(cfg)
+}
+
+/// A signature for "now" from `user.name`/`user.email`, falling back to the
+/// login name when they are not configured.
+pub fn user_signature(repo: &Path) -> Result<Signature> {
+    let cfg = load_config(repo)?;
+    let login = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    let name = cfg.get("user.name").map(str::to_string).unwrap_or_else(|| login.clone());
+    let email = cfg
+        .get("user.email")
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}@localhost", login));
+    Ok(Signature::now(&name, &email))
+}
+
+/// The command used to edit messages: `$RVC_EDITOR`, then `core.editor`,
+/// then `$VISUAL` and `$EDITOR`, and finally `vi`.
+pub fn editor(repo: &Path) -> Result<String> {
+    if let Ok(editor) = std::env::var("RVC_EDITOR") {
+        return Ok(editor);
+    }
+    if let Some(editor) = load_config(repo)?.get("core.editor") {
+        return Ok(editor.to_string());
+    }
+    Ok(std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string()))
+}
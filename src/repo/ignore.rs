@@ -0,0 +1,111 @@
+use ::ignore::gitignore::{Gitignore, GitignoreBuilder};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the per-directory ignore file.
+pub const IGNORE_FILE: &str = ".rvcignore";
+
+/// Repository-local patterns that are not committed, under `.rvc/info/exclude`.
+pub fn exclude_file(repo: &Path) -> PathBuf {
+    crate::core::repo_dir(repo).join("info").join("exclude")
+}
+
+/// The rule that decided whether a path is ignored.
+#[derive(Debug, Clone)]
+pub struct IgnoreMatch {
+    /// File the pattern came from.
+    pub source: PathBuf,
+    /// The pattern as written, including any leading `!`.
+    pub pattern: String,
+    /// `false` for a negated (`!`) pattern that re-includes the path.
+    pub ignored: bool,
+}
+
+/// gitignore-style rules from every `.rvcignore` in the working tree plus
+/// `.rvc/info/exclude`.
+///
+/// Patterns in a deeper `.rvcignore` take precedence over shallower ones, and
+/// all of them over the exclude file; within one file the last matching
+/// pattern wins. As in git, nothing inside an ignored directory can be
+/// re-included. Per-directory files are read lazily as paths are queried.
+pub struct IgnoreRules {
+    repo: PathBuf,
+    exclude: Gitignore,
+    dirs: RefCell<HashMap<String, Gitignore>>,
+}
+
+impl IgnoreRules {
+    pub fn load(repo: &Path) -> Result<Self> {
+        Ok(IgnoreRules {
+            repo: repo.to_path_buf(),
+            exclude: build(repo, &exclude_file(repo))?,
+            dirs: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Whether `path` (relative to the repo, `/`-separated) is ignored.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.check(path, is_dir).is_some_and(|m| m.ignored)
+    }
+
+    /// The rule deciding `path`, if any pattern matches it or a parent directory.
+    pub fn check(&self, path: &str, is_dir: bool) -> Option<IgnoreMatch> {
+        let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+        for depth in 1..parts.len() {
+            if let Some(m) = self.check_one(&parts[..depth], true).filter(|m| m.ignored) {
+                return Some(m);
+            }
+        }
+        self.check_one(&parts, is_dir)
+    }
+
+    /// Matches a single path against the rules, ignoring its parents.
+    fn check_one(&self, parts: &[&str], is_dir: bool) -> Option<IgnoreMatch> {
+        let abs = self.repo.join(parts.join("/"));
+        for depth in (0..parts.len()).rev() {
+            let dir = parts[..depth].join("/");
+            let matched = self.with_dir_rules(&dir, |gi| to_match(gi.matched(&abs, is_dir)));
+            if matched.is_some() {
+                return matched;
+            }
+        }
+        to_match(self.exclude.matched(&abs, is_dir))
+    }
+
+    fn with_dir_rules<T>(&self, dir: &str, f: impl FnOnce(&Gitignore) -> T) -> T {
+        let mut dirs = self.dirs.borrow_mut();
+        let gi = dirs.entry(dir.to_string()).or_insert_with(|| {
+            let root = self.repo.join(dir);
+            // An unreadable or malformed ignore file should not make the
+            // whole working tree unusable; it simply contributes no rules.
+            build(&root, &root.join(IGNORE_FILE)).unwrap_or_else(|_| Gitignore::empty())
+        });
+        f(gi)
+    }
+}
+
+fn build(root: &Path, file: &Path) -> Result<Gitignore> {
+    if !file.is_file() {
+        return Ok(Gitignore::empty());
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(err) = builder.add(file) {
+        return Err(err.into());
+    }
+    Ok(builder.build()?)
+}
+
+fn to_match(m: ::ignore::Match<&::ignore::gitignore::Glob>) -> Option<IgnoreMatch> {
+    let (glob, ignored) = match m {
+        ::ignore::Match::None => return None,
+        ::ignore::Match::Ignore(g) => (g, true),
+        ::ignore::Match::Whitelist(g) => (g, false),
+    };
+    Some(IgnoreMatch {
+        source: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+        pattern: glob.original().to_string(),
+        ignored,
+    })
+}
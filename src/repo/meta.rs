@@ -1,6 +1,5 @@
-use std::collections::HashMap;
 use libp2p::PeerId;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Default)]
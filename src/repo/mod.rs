@@ -1,2 +1,10 @@
 pub mod sync;
 pub mod meta;
+pub mod object_store;
+pub mod config;
+pub mod revparse;
+pub mod refs;
+pub mod worktree;
+pub mod ignore;
+pub mod pathspec;
+pub mod rename;
@@ -0,0 +1,119 @@
+use crate::core::hashing::oid_for_bytes;
+use crate::core::store::{decode_object, encode_object, split_raw_object};
+use crate::core::types::{Object, Oid};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::RwLock;
+
+/// Content-addressed storage for blobs, trees and commits.
+///
+/// Backends only deal in raw `kind\0body` bytes keyed by OID; typed access,
+/// serialization and hash verification are provided on top.
+pub trait ObjectStore {
+    /// Stores raw bytes the caller guarantees hash to `oid`. Storing an
+    /// object that already exists is a no-op.
+    fn write_raw(&self, oid: &Oid, raw: &[u8]) -> Result<()>;
+
+    /// Returns the raw `kind\0body` bytes of an object, if present.
+    fn get_raw(&self, oid: &Oid) -> Result<Option<Vec<u8>>>;
+
+    fn contains(&self, oid: &Oid) -> Result<bool>;
+
+    /// Lists every object in the store, in no particular order.
+    fn list_oids(&self) -> Result<Vec<Oid>>;
+
+    fn put(&self, obj: &Object) -> Result<Oid> {
+        let (oid, raw) = encode_object(obj);
+        self.write_raw(&oid, &raw)?;
+        Ok(oid)
+    }
+
+    fn get(&self, oid: &Oid) -> Result<Option<Object>> {
+        match self.get_raw(oid)? {
+            Some(raw) => Ok(Some(decode_object(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores a blob read from `reader`. Backends that can should override
+    /// this to hash and write incrementally instead of buffering the blob.
+    fn put_blob_stream(&self, reader: &mut dyn Read) -> Result<Oid> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        self.put(&Object::Blob(buf))
+    }
+
+    /// Opens an object's raw `kind\0body` bytes for reading. Backends that
+    /// can should override this to stream instead of loading the object whole.
+    fn open_raw(&self, oid: &Oid) -> Result<Option<Box<dyn Read + '_>>> {
+        Ok(self.get_raw(oid)?.map(|raw| Box::new(Cursor::new(raw)) as Box<dyn Read>))
+    }
+
+    /// Opens a blob for reading. Backends that can should override this to
+    /// stream the content instead of loading it whole.
+    fn open_blob(&self, oid: &Oid) -> Result<Option<Box<dyn Read + '_>>> {
+        match self.get_raw(oid)? {
+            Some(raw) => Ok(Some(blob_reader_from_raw(oid, raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores raw bytes received from elsewhere, refusing them if they do
+    /// not hash to `oid`.
+    fn put_raw(&self, oid: &Oid, raw: &[u8]) -> Result<()> {
+        let (kind, body) = split_raw_object(raw)?;
+        let actual = oid_for_bytes(kind, body);
+        if actual != *oid {
+            return Err(anyhow::anyhow!("object hash mismatch: expected {}, got {}", oid, actual));
+        }
+        self.write_raw(oid, raw)
+    }
+}
+
+pub(crate) fn blob_reader_from_raw(oid: &Oid, raw: Vec<u8>) -> Result<Box<dyn Read>> {
+    let (kind, body) = split_raw_object(&raw)?;
+    if kind != "blob" {
+        return Err(anyhow::anyhow!("object {} is a {}, not a blob", oid, kind));
+    }
+    let header_len = raw.len() - body.len();
+    let mut cursor = Cursor::new(raw);
+    cursor.set_position(header_len as u64);
+    Ok(Box::new(cursor))
+}
+
+/// Object store kept entirely in memory, for tests and embedders that
+/// manage persistence themselves.
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    objects: RwLock<HashMap<Oid, Vec<u8>>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn write_raw(&self, oid: &Oid, raw: &[u8]) -> Result<()> {
+        self.objects
+            .write()
+            .unwrap()
+            .entry(*oid)
+            .or_insert_with(|| raw.to_vec());
+        Ok(())
+    }
+
+    fn get_raw(&self, oid: &Oid) -> Result<Option<Vec<u8>>> {
+        Ok(self.objects.read().unwrap().get(oid).cloned())
+    }
+
+    fn contains(&self, oid: &Oid) -> Result<bool> {
+        Ok(self.objects.read().unwrap().contains_key(oid))
+    }
+
+    fn list_oids(&self) -> Result<Vec<Oid>> {
+        Ok(self.objects.read().unwrap().keys().copied().collect())
+    }
+}
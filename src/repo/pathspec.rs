@@ -0,0 +1,33 @@
+use crate::repo::worktree;
+use anyhow::Result;
+use globset::{Glob, GlobMatcher};
+
+/// One command-line pathspec: a file or directory prefix, or a glob.
+pub enum Pathspec {
+    Prefix(String),
+    Glob(GlobMatcher),
+}
+
+impl Pathspec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim_start_matches("./").trim_end_matches('/');
+        if spec.contains(['*', '?', '[']) {
+            Ok(Pathspec::Glob(Glob::new(spec)?.compile_matcher()))
+        } else if spec == "." {
+            Ok(Pathspec::Prefix(String::new()))
+        } else {
+            Ok(Pathspec::Prefix(spec.to_string()))
+        }
+    }
+
+    pub fn matches(&self, path: &[u8]) -> bool {
+        match self {
+            Pathspec::Prefix(p) => {
+                p.is_empty()
+                    || path == p.as_bytes()
+                    || path.strip_prefix(p.as_bytes()).is_some_and(|rest| rest.starts_with(b"/"))
+            }
+            Pathspec::Glob(g) => g.is_match(worktree::from_repo_path(path)),
+        }
+    }
+}
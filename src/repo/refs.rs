@@ -0,0 +1,171 @@
+use crate::core::types::{head_file, refs_heads_dir, Oid};
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Branch a fresh repository starts on.
+pub const DEFAULT_BRANCH: &str = "main";
+
+const SYMREF_PREFIX: &str = "ref: refs/heads/";
+
+/// What `HEAD` points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// On a branch, which may not have any commits yet.
+    Branch(String),
+    /// Detached at a specific commit.
+    Detached(Oid),
+}
+
+/// Reads `HEAD`. An empty or missing file (written by older versions before
+/// the first commit) counts as the default branch.
+pub fn read_head(repo: &Path) -> Result<Head> {
+    let content = match fs::read_to_string(head_file(repo)) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let content = content.trim();
+
+    if content.is_empty() {
+        Ok(Head::Branch(DEFAULT_BRANCH.to_string()))
+    } else if let Some(name) = content.strip_prefix(SYMREF_PREFIX) {
+        Ok(Head::Branch(name.to_string()))
+    } else {
+        Ok(Head::Detached(Oid::from_hex(content)?))
+    }
+}
+
+/// The commit `HEAD` resolves to, or `None` on a branch with no commits.
+pub fn head_commit(repo: &Path) -> Result<Option<Oid>> {
+    match read_head(repo)? {
+        Head::Branch(name) => read_branch(repo, &name),
+        Head::Detached(oid) => Ok(Some(oid)),
+    }
+}
+
+/// The checked-out branch, or `None` when `HEAD` is detached.
+pub fn current_branch(repo: &Path) -> Result<Option<String>> {
+    match read_head(repo)? {
+        Head::Branch(name) => Ok(Some(name)),
+        Head::Detached(_) => Ok(None),
+    }
+}
+
+/// Points `HEAD` at a branch without touching the branch itself.
+pub fn set_head_branch(repo: &Path, name: &str) -> Result<()> {
+    check_branch_name(name)?;
+    fs::write(head_file(repo), format!("{}{}", SYMREF_PREFIX, name))?;
+    Ok(())
+}
+
+/// Detaches `HEAD` at the given commit.
+pub fn detach_head(repo: &Path, oid: &Oid) -> Result<()> {
+    fs::write(head_file(repo), oid.to_hex())?;
+    Ok(())
+}
+
+/// Moves whatever `HEAD` points at to `oid`: the current branch, or `HEAD`
+/// itself when detached.
+pub fn update_head(repo: &Path, oid: &Oid) -> Result<()> {
+    match read_head(repo)? {
+        Head::Branch(name) => write_branch(repo, &name, oid),
+        Head::Detached(_) => detach_head(repo, oid),
+    }
+}
+
+pub fn branch_path(repo: &Path, name: &str) -> PathBuf {
+    refs_heads_dir(repo).join(name)
+}
+
+pub fn read_branch(repo: &Path, name: &str) -> Result<Option<Oid>> {
+    check_branch_name(name)?;
+    let path = branch_path(repo, name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Oid::from_hex(content.trim())?))
+}
+
+pub fn write_branch(repo: &Path, name: &str, oid: &Oid) -> Result<()> {
+    check_branch_name(name)?;
+    let path = branch_path(repo, name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, oid.to_hex())?;
+    Ok(())
+}
+
+/// Removes a branch ref and any directories left empty by it.
+pub fn remove_branch(repo: &Path, name: &str) -> Result<()> {
+    check_branch_name(name)?;
+    let heads = refs_heads_dir(repo);
+    let path = branch_path(repo, name);
+    fs::remove_file(&path)?;
+
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == heads || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+/// All branches with their tips, sorted by name. Names may contain `/`.
+pub fn list_branches(repo: &Path) -> Result<Vec<(String, Oid)>> {
+    let heads = refs_heads_dir(repo);
+    let mut out = Vec::new();
+    if !heads.exists() {
+        return Ok(out);
+    }
+    for entry in walkdir::WalkDir::new(&heads).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(&heads)?;
+        let name = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        if let Some(oid) = read_branch(repo, &name).ok().flatten() {
+            out.push((name, oid));
+        }
+    }
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(out)
+}
+
+/// Rejects names that would be ambiguous or escape `refs/heads`.
+pub fn check_branch_name(name: &str) -> Result<()> {
+    let bad = name.is_empty()
+        || name == "HEAD"
+        || name.starts_with('-')
+        || name.ends_with(".lock")
+        || name.split('/').any(|part| part.is_empty() || part.starts_with('.'))
+        || name.contains("..")
+        || name.chars().any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c));
+    if bad {
+        return Err(anyhow::anyhow!("invalid branch name: {:?}", name));
+    }
+    Ok(())
+}
+
+/// Converts a `HEAD` holding a raw commit hash, as written by older versions,
+/// into the default branch when no branches exist yet. Returns whether
+/// anything changed.
+pub fn migrate_legacy_head(repo: &Path) -> Result<bool> {
+    let oid = match read_head(repo)? {
+        Head::Detached(oid) => oid,
+        Head::Branch(_) => return Ok(false),
+    };
+    if !list_branches(repo)?.is_empty() {
+        return Ok(false);
+    }
+    write_branch(repo, DEFAULT_BRANCH, &oid)?;
+    set_head_branch(repo, DEFAULT_BRANCH)?;
+    Ok(true)
+}
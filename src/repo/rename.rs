@@ -0,0 +1,128 @@
+use crate::core::Oid;
+use crate::repo::object_store::ObjectStore;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+/// Minimum similarity, in percent, for a delete/add pair to count as a rename.
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// Blobs larger than this are only paired by exact id, never by similarity.
+const MAX_SIMILARITY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Content comparison is skipped when it would need more pairs than this.
+const MAX_SIMILARITY_PAIRS: usize = 250_000;
+
+/// Chunks longer than this are split, so binary data without newlines still compares.
+const MAX_CHUNK: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: Vec<u8>,
+    pub to: Vec<u8>,
+    /// Similarity in percent; 100 means identical content.
+    pub score: u8,
+}
+
+/// Pairs deleted paths with added ones. Identical blobs are matched first;
+/// the rest are matched greedily by content similarity, best score first, as
+/// long as it reaches `threshold` percent. Each path is used at most once.
+pub fn detect_renames(
+    store: &dyn ObjectStore,
+    deleted: &[(Vec<u8>, Oid)],
+    added: &[(Vec<u8>, Oid)],
+    threshold: u8,
+) -> Result<Vec<Rename>> {
+    let mut renames = Vec::new();
+    let mut used_from = HashSet::new();
+    let mut used_to = HashSet::new();
+
+    let mut by_oid: HashMap<Oid, Vec<&Vec<u8>>> = HashMap::new();
+    for (path, oid) in deleted {
+        by_oid.entry(*oid).or_default().push(path);
+    }
+    for (to, oid) in added {
+        if let Some(from) = by_oid.get_mut(oid).and_then(|paths| (!paths.is_empty()).then(|| paths.remove(0))) {
+            used_from.insert(from.clone());
+            used_to.insert(to.clone());
+            renames.push(Rename { from: from.clone(), to: to.clone(), score: 100 });
+        }
+    }
+
+    let rest_from: Vec<&(Vec<u8>, Oid)> = deleted.iter().filter(|(p, _)| !used_from.contains(p)).collect();
+    let rest_to: Vec<&(Vec<u8>, Oid)> = added.iter().filter(|(p, _)| !used_to.contains(p)).collect();
+    if rest_from.is_empty() || rest_to.is_empty() || rest_from.len() * rest_to.len() > MAX_SIMILARITY_PAIRS {
+        return Ok(renames);
+    }
+
+    let from_sigs = rest_from.iter().map(|(_, oid)| signature(store, oid)).collect::<Result<Vec<_>>>()?;
+    let to_sigs = rest_to.iter().map(|(_, oid)| signature(store, oid)).collect::<Result<Vec<_>>>()?;
+
+    let mut candidates = Vec::new();
+    for (i, from) in from_sigs.iter().enumerate() {
+        for (j, to) in to_sigs.iter().enumerate() {
+            if let (Some(from), Some(to)) = (from, to) {
+                let score = similarity(from, to);
+                if score >= threshold {
+                    candidates.push((score, i, j));
+                }
+            }
+        }
+    }
+    // Highest score first; ties keep path order so results are stable.
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut taken_from = HashSet::new();
+    let mut taken_to = HashSet::new();
+    for (score, i, j) in candidates {
+        if taken_from.contains(&i) || taken_to.contains(&j) {
+            continue;
+        }
+        taken_from.insert(i);
+        taken_to.insert(j);
+        renames.push(Rename { from: rest_from[i].0.clone(), to: rest_to[j].0.clone(), score });
+    }
+    Ok(renames)
+}
+
+/// Content fingerprint: total size and byte counts per chunk hash.
+struct Signature {
+    size: usize,
+    chunks: HashMap<u64, usize>,
+}
+
+fn signature(store: &dyn ObjectStore, oid: &Oid) -> Result<Option<Signature>> {
+    // Read through the streaming reader and stop one byte past the limit, so
+    // a large blob is never loaded just to find out it is too large.
+    let Some(reader) = store.open_blob(oid)? else {
+        return Ok(None);
+    };
+    let mut data = Vec::new();
+    reader.take(MAX_SIMILARITY_SIZE as u64 + 1).read_to_end(&mut data)?;
+    if data.len() > MAX_SIMILARITY_SIZE {
+        return Ok(None);
+    }
+
+    let mut chunks = HashMap::new();
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        for chunk in line.chunks(MAX_CHUNK) {
+            let hash = u64::from_le_bytes(blake3::hash(chunk).as_bytes()[..8].try_into()?);
+            *chunks.entry(hash).or_insert(0) += chunk.len();
+        }
+    }
+    Ok(Some(Signature { size: data.len(), chunks }))
+}
+
+/// Percentage of the larger file's bytes that appear in both.
+fn similarity(a: &Signature, b: &Signature) -> u8 {
+    let larger = a.size.max(b.size);
+    if larger == 0 {
+        return 100;
+    }
+    let common: usize = a
+        .chunks
+        .iter()
+        .filter_map(|(hash, &n)| b.chunks.get(hash).map(|&m| n.min(m)))
+        .sum();
+    (common * 100 / larger) as u8
+}
@@ -0,0 +1,124 @@
+use crate::core::types::{Commit, Object, Oid};
+use crate::repo::object_store::ObjectStore;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+/// Shortest hex prefix accepted as an abbreviated object id.
+pub const MIN_PREFIX_LEN: usize = 4;
+
+/// Resolves a revision such as `HEAD`, `main`, `a1b2c3`, `HEAD~2` or `main^2`
+/// to a full object id.
+///
+/// The base name is looked up as `HEAD`, then a full 64-digit hex id, then a
+/// ref under `.rvc` (`refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`),
+/// and only then as an abbreviated hex id, so a branch named `cafe` wins over
+/// an object whose id starts with `cafe`. Any trailing `~n` follows first parents `n` times;
+/// `^n` selects the `n`th parent (`^0` is the commit itself). A bare `~` or
+/// `^` means `1`.
+pub fn resolve_revision(repo: &Path, store: &dyn ObjectStore, spec: &str) -> Result<Oid> {
+    let split = spec.find(['~', '^']).unwrap_or(spec.len());
+    let (base, mut suffix) = spec.split_at(split);
+    if base.is_empty() {
+        return Err(anyhow::anyhow!("invalid revision: {}", spec));
+    }
+
+    let mut oid = resolve_name(repo, store, base)?;
+    while let Some(&op) = suffix.as_bytes().first() {
+        if op != b'~' && op != b'^' {
+            return Err(anyhow::anyhow!("invalid revision: {}", spec));
+        }
+        let digits = suffix[1..].bytes().take_while(u8::is_ascii_digit).count();
+        let n = match &suffix[1..1 + digits] {
+            "" => 1,
+            d => d.parse::<usize>()?,
+        };
+        suffix = &suffix[1 + digits..];
+
+        if op == b'~' {
+            for _ in 0..n {
+                oid = nth_parent(store, &oid, 1, spec)?;
+            }
+        } else if n == 0 {
+            read_commit(store, &oid)?;
+        } else {
+            oid = nth_parent(store, &oid, n, spec)?;
+        }
+    }
+    Ok(oid)
+}
+
+/// Like [`resolve_revision`], but also requires the result to be a commit.
+pub fn resolve_commit(repo: &Path, store: &dyn ObjectStore, spec: &str) -> Result<(Oid, Commit)> {
+    let oid = resolve_revision(repo, store, spec)?;
+    let commit = read_commit(store, &oid).map_err(|_| anyhow::anyhow!("{} is not a commit", spec))?;
+    Ok((oid, commit))
+}
+
+fn resolve_name(repo: &Path, store: &dyn ObjectStore, name: &str) -> Result<Oid> {
+    if name == "HEAD" {
+        return crate::repo::refs::head_commit(repo)?
+            .ok_or_else(|| anyhow::anyhow!("HEAD does not point to a commit yet"));
+    }
+
+    if name.len() == 64 {
+        if let Ok(oid) = Oid::from_hex(name) {
+            return Ok(oid);
+        }
+    }
+
+    let rvc = crate::core::repo_dir(repo);
+    // `..` would let a name escape the refs directory.
+    if !name.split('/').any(|part| part == ".." || part.is_empty()) {
+        for candidate in [format!("refs/{}", name), format!("refs/tags/{}", name), format!("refs/heads/{}", name)] {
+            if let Some(oid) = read_ref_file(&rvc.join(candidate))? {
+                return Ok(oid);
+            }
+        }
+    }
+
+    if name.len() >= MIN_PREFIX_LEN && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return resolve_prefix(store, &name.to_ascii_lowercase());
+    }
+
+    Err(anyhow::anyhow!("unknown revision: {}", name))
+}
+
+/// Finds the single object whose id starts with `prefix`.
+fn resolve_prefix(store: &dyn ObjectStore, prefix: &str) -> Result<Oid> {
+    let mut matches = store.list_oids()?.into_iter().filter(|o| o.to_hex().starts_with(prefix));
+    match (matches.next(), matches.next()) {
+        (Some(oid), None) => Ok(oid),
+        (None, _) => Err(anyhow::anyhow!("unknown revision: {}", prefix)),
+        (Some(_), Some(_)) => Err(anyhow::anyhow!("short object id {} is ambiguous", prefix)),
+    }
+}
+
+fn read_ref_file(path: &Path) -> Result<Option<Oid>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let hash = content.trim();
+    if hash.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Oid::from_hex(hash)?))
+}
+
+fn read_commit(store: &dyn ObjectStore, oid: &Oid) -> Result<Commit> {
+    match store.get(oid)? {
+        Some(Object::Commit(c)) => Ok(c),
+        Some(_) => Err(anyhow::anyhow!("{} is not a commit", oid)),
+        None => Err(anyhow::anyhow!("object not found: {}", oid)),
+    }
+}
+
+fn nth_parent(store: &dyn ObjectStore, oid: &Oid, n: usize, spec: &str) -> Result<Oid> {
+    let commit = read_commit(store, oid)?;
+    let parent = commit
+        .parents
+        .get(n - 1)
+        .ok_or_else(|| anyhow::anyhow!("revision {} goes past the available history", spec))?;
+    Oid::from_hex(parent)
+}
@@ -1,17 +1,53 @@
 use std::collections::{HashMap, HashSet};
-use crate::core::store::FsObjectStore;
-use crate::core::types::{Object, Oid};
-use std::path::{Path, PathBuf};
+use crate::core::pack::{plan_deltas, write_pack, PackIndex};
+use crate::core::store::{flatten_tree, write_tree_from_paths};
+use crate::repo::object_store::ObjectStore;
+use crate::core::types::{Object, Oid, Signature};
+use crate::repo::refs::{self, Head};
+use crate::repo::rename::{detect_renames, Rename, DEFAULT_RENAME_THRESHOLD};
+use crate::repo::worktree::display_path;
+use anyhow::Result;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Collects the commits and trees reachable from the local heads. Everything
+/// below them is already present locally, so the remote walk can skip them.
+fn local_closure(store: &dyn ObjectStore, local_heads: &HashMap<String, String>) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut queue: Vec<String> = local_heads.values().cloned().collect();
+
+    while let Some(hash_str) = queue.pop() {
+        if seen.contains(&hash_str) { continue; }
+        let oid = match Oid::from_hex(&hash_str) {
+            Ok(o) => o,
+            Err(_) => continue,
+        };
+        match store.get(&oid) {
+            Ok(Some(Object::Commit(c))) => {
+                seen.insert(hash_str);
+                queue.extend(c.parents);
+                queue.push(c.tree);
+            }
+            Ok(Some(Object::Tree(entries))) => {
+                seen.insert(hash_str);
+                queue.extend(entries.into_iter().filter(|e| e.is_dir()).map(|e| e.oid.to_hex()));
+            }
+            _ => {}
+        }
+    }
+    seen
+}
 
 pub fn find_missing_objects(
-    repo: &Path,
-    _local_heads: HashMap<String, String>,
+    store: &dyn ObjectStore,
+    local_heads: HashMap<String, String>,
     remote_heads: HashMap<String, String>,
 ) -> Vec<String> {
-    let store = FsObjectStore::new(repo);
     let mut missing = Vec::new();
     let mut queue: Vec<String> = remote_heads.values().cloned().collect();
-    let mut visited = HashSet::new();
+    // Unchanged subtrees keep their OIDs, so anything the local heads reach is complete.
+    let mut visited = local_closure(store, &local_heads);
 
     while let Some(hash_str) = queue.pop() {
         if visited.contains(&hash_str) { continue; }
@@ -49,15 +85,19 @@ pub fn find_missing_objects(
     missing
 }
 
-pub fn get_objects(repo: &Path, hashes: Vec<String>) -> Vec<(String, Vec<u8>)> {
+pub fn get_objects(store: &dyn ObjectStore, hashes: Vec<String>) -> Vec<(String, Vec<u8>)> {
     println!("Serving {} requested objects", hashes.len());
     let mut res = Vec::new();
     for hash_str in hashes {
-        let path = crate::core::types::objects_dir(repo).join(&hash_str);
-        match std::fs::read(&path) {
-            Ok(data) => {
+        // Objects travel uncompressed so the receiver can verify them against their OID.
+        let raw = Oid::from_hex(&hash_str).and_then(|oid| store.get_raw(&oid));
+        match raw {
+            Ok(Some(data)) => {
                 res.push((hash_str, data));
             }
+            Ok(None) => {
+                println!("Warning: Object {} not found", hash_str);
+            }
             Err(e) => {
                 println!("Warning: Failed to read object {}: {:?}", hash_str, e);
             }
@@ -66,59 +106,95 @@ pub fn get_objects(repo: &Path, hashes: Vec<String>) -> Vec<(String, Vec<u8>)> {
     res
 }
 
-pub fn store_objects(repo: &Path, objects: Vec<(String, Vec<u8>)>) {
+pub fn store_objects(store: &dyn ObjectStore, objects: Vec<(String, Vec<u8>)>) {
     println!("Storing {} objects...", objects.len());
     for (hash_str, data) in objects {
-        let path = crate::core::types::objects_dir(repo).join(&hash_str);
-        if !path.exists() {
-            if let Some(parent) = path.parent() {
-                let _ = std::fs::create_dir_all(parent);
-            }
-            if let Err(e) = std::fs::write(&path, data) {
-                println!("Error storing object {}: {:?}", hash_str, e);
-            } else {
-                println!("Stored object {}", hash_str);
-            }
+        let stored = Oid::from_hex(&hash_str).and_then(|oid| store.put_raw(&oid, &data));
+        if let Err(e) = stored {
+            println!("Error storing object {}: {:?}", hash_str, e);
+        } else {
+            println!("Stored object {}", hash_str);
+        }
+    }
+}
+
+/// Packs the requested objects that exist locally for sending to a peer.
+/// Older versions of a file are stored as deltas against newer ones fetched
+/// in the same request. Returns the `.pack` and `.idx` bytes.
+pub fn pack_objects(repo: &Path, store: &dyn ObjectStore, hashes: &[String]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut oids = Vec::new();
+    for hash_str in hashes {
+        match Oid::from_hex(hash_str) {
+            Ok(oid) if store.contains(&oid)? => oids.push(oid),
+            _ => println!("Warning: Object {} not found", hash_str),
         }
     }
+
+    let heads: Vec<Oid> = get_local_refs(repo).values().filter_map(|h| Oid::from_hex(h).ok()).collect();
+    let wanted: HashSet<Oid> = oids.iter().copied().collect();
+    let bases = plan_deltas(store, &heads, Some(&wanted))?;
+    let dir = tempfile::tempdir()?;
+    let pack_path = write_pack(dir.path(), store, &oids, &bases)?;
+    Ok((fs::read(&pack_path)?, fs::read(pack_path.with_extension("idx"))?))
+}
+
+/// Stores every object in a pack received from a peer, verifying the pack
+/// checksum and each object against its OID. Returns how many objects the
+/// pack held.
+pub fn unpack_objects(store: &dyn ObjectStore, pack: &[u8], index: &[u8]) -> Result<usize> {
+    let dir = tempfile::tempdir()?;
+    let idx_path = dir.path().join("received.idx");
+    fs::write(idx_path.with_extension("pack"), pack)?;
+    fs::write(&idx_path, index)?;
+
+    let received = PackIndex::load(&idx_path)?;
+    received.verify()?;
+    let mut count = 0;
+    for oid in received.oids() {
+        let raw = received
+            .read_raw(oid)?
+            .ok_or_else(|| anyhow::anyhow!("object {} missing from received pack", oid))?;
+        store.put_raw(oid, &raw)?;
+        count += 1;
+    }
+    Ok(count)
 }
 
+/// HEAD (resolved to its commit) plus every branch, keyed by branch name.
 pub fn get_local_refs(repo: &Path) -> HashMap<String, String> {
     let mut refs = HashMap::new();
-    if let Ok(head) = std::fs::read_to_string(crate::core::types::head_file(repo)) {
-        if !head.trim().is_empty() {
-             refs.insert("HEAD".to_string(), head.trim().to_string());
-        }
+    if let Ok(Some(head)) = refs::head_commit(repo) {
+        refs.insert("HEAD".to_string(), head.to_hex());
     }
-    let heads_dir = crate::core::types::refs_heads_dir(repo);
-    if let Ok(entries) = std::fs::read_dir(heads_dir) {
-        for entry in entries.filter_map(Result::ok) {
-            if let Ok(content) = std::fs::read_to_string(entry.path()) {
-                let name = entry.file_name().to_string_lossy().to_string();
-                refs.insert(name, content.trim().to_string());
-            }
-        }
+    for (name, oid) in refs::list_branches(repo).unwrap_or_default() {
+        refs.insert(name, oid.to_hex());
     }
     refs
 }
 
+/// Writes synced refs back. A `HEAD` entry moves the checked-out branch only
+/// when that branch is not itself in `refs`, and names that are not valid
+/// branch names are ignored.
 pub fn update_refs(repo: &Path, refs: &HashMap<String, String>) {
-    let heads_dir = crate::core::types::refs_heads_dir(repo);
-    let _ = std::fs::create_dir_all(&heads_dir);
+    let head = refs::read_head(repo).ok();
     for (name, hash) in refs {
+        let Ok(oid) = Oid::from_hex(hash) else { continue };
         if name == "HEAD" {
-            let _ = std::fs::write(crate::core::types::head_file(repo), hash);
-        } else {
-            let _ = std::fs::write(heads_dir.join(name), hash);
+            let _ = match &head {
+                Some(Head::Branch(branch)) if refs.contains_key(branch) => continue,
+                Some(Head::Branch(branch)) => refs::write_branch(repo, branch, &oid),
+                _ => refs::detach_head(repo, &oid),
+            };
+        } else if refs::check_branch_name(name).is_ok() {
+            let _ = refs::write_branch(repo, name, &oid);
         }
     }
 }
 
-pub fn is_descendant(repo: &Path, descendant_hash: &str, ancestor_hash: &str) -> bool {
+pub fn is_descendant(store: &dyn ObjectStore, descendant_hash: &str, ancestor_hash: &str) -> bool {
     if descendant_hash == ancestor_hash {
         return true;
     }
-    let store = FsObjectStore::new(repo);
     let mut queue = vec![descendant_hash.to_string()];
     let mut visited = HashSet::new();
 
@@ -142,12 +218,57 @@ pub fn is_descendant(repo: &Path, descendant_hash: &str, ancestor_hash: &str) ->
     false
 }
 
+/// A path the three-way merge could not resolve on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// Both sides changed the file, differently.
+    Content(Vec<u8>),
+    /// One side changed the file and the other deleted it.
+    ModifyDelete(Vec<u8>),
+    /// One side renamed the file and the other deleted it.
+    RenameDelete { from: Vec<u8>, to: Vec<u8> },
+    /// Each side renamed the same file to a different path.
+    RenameRename { from: Vec<u8>, local: Vec<u8>, remote: Vec<u8> },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = display_path;
+        match self {
+            MergeConflict::Content(path) => write!(f, "both modified: {}", show(path)),
+            MergeConflict::ModifyDelete(path) => write!(f, "modified and deleted: {}", show(path)),
+            MergeConflict::RenameDelete { from, to } => {
+                write!(f, "renamed and deleted: {} -> {}", show(from), show(to))
+            }
+            MergeConflict::RenameRename { from, local, remote } => write!(
+                f,
+                "renamed differently: {} -> {} (local), {} (remote)",
+                show(from),
+                show(local),
+                show(remote)
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MergeOutcome {
+    /// The merge commit that was written.
+    Merged(String),
+    /// Nothing was written; these paths need resolving by hand.
+    Conflicts(Vec<MergeConflict>),
+}
+
+/// Three-way merges `remote_hash` into `local_hash` against their merge
+/// base. A merge commit is only written when every path resolves cleanly;
+/// otherwise the conflicts are returned. `None` means the commits or trees
+/// could not be read.
 pub fn create_merge_commit(
-    repo: &Path,
+    store: &dyn ObjectStore,
     local_hash: &str,
     remote_hash: &str,
-) -> Option<String> {
-    let store = FsObjectStore::new(repo);
+    signature: &Signature,
+) -> Option<MergeOutcome> {
     let local_oid = Oid::from_hex(local_hash).ok()?;
     let remote_oid = Oid::from_hex(remote_hash).ok()?;
 
@@ -160,39 +281,166 @@ pub fn create_merge_commit(
         _ => return None,
     };
 
-    let mut merged_entries = std::collections::HashMap::new();
-    
-    if let Ok(remote_tree_oid) = Oid::from_hex(&remote_commit.tree) {
-        if let Ok(Some(Object::Tree(entries))) = store.get(&remote_tree_oid) {
-            for e in entries {
-                merged_entries.insert(e.name.clone(), e);
-            }
-        }
+    let tree_of = |commit: &crate::core::types::Commit| -> Option<FileMap> {
+        let tree = Oid::from_hex(&commit.tree).ok()?;
+        Some(flatten_tree(store, &tree).ok()?.into_iter().map(|e| (e.name, (e.oid, e.mode))).collect())
+    };
+    let mut local = tree_of(&local_commit)?;
+    let mut remote = tree_of(&remote_commit)?;
+    let mut base = match merge_base(store, &local_oid, &remote_oid) {
+        Some(oid) => match store.get(&oid).ok()? {
+            Some(Object::Commit(c)) => tree_of(&c)?,
+            _ => return None,
+        },
+        None => FileMap::new(),
+    };
+
+    let local_renames = side_renames(store, &base, &local)?;
+    let remote_renames = side_renames(store, &base, &remote)?;
+    let mut conflicts = rename_conflicts(&local_renames, &remote_renames, &remote);
+    conflicts.extend(
+        rename_conflicts(&remote_renames, &local_renames, &local)
+            .into_iter()
+            // Rename/rename pairs are already reported from the local side.
+            .filter(|c| matches!(c, MergeConflict::RenameDelete { .. })),
+    );
+
+    // A file renamed on one side but still at its old path on the other is
+    // moved on the other side too, so edits made there follow the rename
+    // instead of resurrecting the old path next to the new one.
+    for rename in &local_renames {
+        follow_rename(&mut base, &mut remote, rename);
     }
-    
-    if let Ok(local_tree_oid) = Oid::from_hex(&local_commit.tree) {
-        if let Ok(Some(Object::Tree(entries))) = store.get(&local_tree_oid) {
-            for e in entries {
-                merged_entries.insert(e.name.clone(), e);
-            }
-        }
+    for rename in &remote_renames {
+        follow_rename(&mut base, &mut local, rename);
     }
 
-    let mut new_entries: Vec<crate::core::types::TreeEntry> = merged_entries.into_values().collect();
-    new_entries.sort_by(|a, b| a.name.cmp(&b.name));
+    // Three-way per path: take whichever side changed it; if both did, the
+    // changes must agree.
+    let mut paths: Vec<&Vec<u8>> = base.keys().chain(local.keys()).chain(remote.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    let mut new_entries = Vec::new();
+    for path in paths {
+        let (b, l, r) = (base.get(path), local.get(path), remote.get(path));
+        let chosen = if l == b || l == r {
+            r
+        } else if r == b {
+            l
+        } else {
+            conflicts.push(match (l, r) {
+                (Some(_), Some(_)) => MergeConflict::Content(path.clone()),
+                _ => MergeConflict::ModifyDelete(path.clone()),
+            });
+            continue;
+        };
+        if let Some(&(oid, mode)) = chosen {
+            new_entries.push(crate::core::types::TreeEntry { mode, name: path.clone(), oid });
+        }
+    }
+    if !conflicts.is_empty() {
+        return Some(MergeOutcome::Conflicts(conflicts));
+    }
 
-    let tree_oid = store.put(&Object::Tree(new_entries)).ok()?;
+    let tree_oid = write_tree_from_paths(store, &new_entries).ok()?;
 
-    let ts = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
-    
     let merge_commit = crate::core::types::Commit {
         tree: tree_oid.to_hex(),
         parents: vec![local_hash.to_string(), remote_hash.to_string()],
-        author: "minigit-sync <sync@example.com>".to_string(),
+        author: signature.clone(),
+        committer: signature.clone(),
         message: format!("Merge remote {} into {}", remote_hash, local_hash),
-        timestamp: ts,
     };
 
     let commit_oid = store.put(&Object::Commit(merge_commit)).ok()?;
-    Some(commit_oid.to_hex())
+    Some(MergeOutcome::Merged(commit_oid.to_hex()))
+}
+
+/// Conflicts caused by `renames` on one side: the other side renamed the
+/// same file elsewhere, or deleted it.
+fn rename_conflicts(renames: &[Rename], other_renames: &[Rename], other: &FileMap) -> Vec<MergeConflict> {
+    let mut conflicts = Vec::new();
+    for rename in renames {
+        match other_renames.iter().find(|o| o.from == rename.from) {
+            Some(o) if o.to != rename.to => conflicts.push(MergeConflict::RenameRename {
+                from: rename.from.clone(),
+                local: rename.to.clone(),
+                remote: o.to.clone(),
+            }),
+            Some(_) => {}
+            None if !other.contains_key(&rename.from) => conflicts.push(MergeConflict::RenameDelete {
+                from: rename.from.clone(),
+                to: rename.to.clone(),
+            }),
+            None => {}
+        }
+    }
+    conflicts
+}
+
+/// Path -> (blob id, mode) for every file in a tree.
+type FileMap = HashMap<Vec<u8>, (Oid, u32)>;
+
+/// The nearest commit reachable from both `a` and `b`, if they share history.
+pub fn merge_base(store: &dyn ObjectStore, a: &Oid, b: &Oid) -> Option<Oid> {
+    let ancestors_of_a = ancestors(store, a);
+    let mut queue = std::collections::VecDeque::from([*b]);
+    let mut seen = HashSet::new();
+    while let Some(cur) = queue.pop_front() {
+        if ancestors_of_a.contains(&cur) {
+            return Some(cur);
+        }
+        if !seen.insert(cur) {
+            continue;
+        }
+        if let Ok(Some(Object::Commit(c))) = store.get(&cur) {
+            queue.extend(c.parents.iter().filter_map(|p| Oid::from_hex(p).ok()));
+        }
+    }
+    None
+}
+
+fn ancestors(store: &dyn ObjectStore, start: &Oid) -> HashSet<Oid> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![*start];
+    while let Some(cur) = stack.pop() {
+        if !seen.insert(cur) {
+            continue;
+        }
+        if let Ok(Some(Object::Commit(c))) = store.get(&cur) {
+            stack.extend(c.parents.iter().filter_map(|p| Oid::from_hex(p).ok()));
+        }
+    }
+    seen
+}
+
+/// Renames made between `base` and `side`.
+fn side_renames(store: &dyn ObjectStore, base: &FileMap, side: &FileMap) -> Option<Vec<Rename>> {
+    let mut deleted: Vec<(Vec<u8>, Oid)> = base
+        .iter()
+        .filter(|(p, _)| !side.contains_key(*p))
+        .map(|(p, (oid, _))| (p.clone(), *oid))
+        .collect();
+    let mut added: Vec<(Vec<u8>, Oid)> = side
+        .iter()
+        .filter(|(p, _)| !base.contains_key(*p))
+        .map(|(p, (oid, _))| (p.clone(), *oid))
+        .collect();
+    deleted.sort();
+    added.sort();
+    detect_renames(store, &deleted, &added, DEFAULT_RENAME_THRESHOLD).ok()
+}
+
+/// Applies a rename from one side to `base` and the `other` side, when the
+/// other side still has the file at its old path and nothing at the new one.
+fn follow_rename(base: &mut FileMap, other: &mut FileMap, rename: &Rename) {
+    if other.contains_key(&rename.to) || !other.contains_key(&rename.from) {
+        return;
+    }
+    if let Some(entry) = other.remove(&rename.from) {
+        other.insert(rename.to.clone(), entry);
+    }
+    if let Some(entry) = base.remove(&rename.from) {
+        base.insert(rename.to.clone(), entry);
+    }
 }
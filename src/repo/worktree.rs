@@ -0,0 +1,193 @@
+use crate::repo::ignore::IgnoreRules;
+use crate::core::{Oid, MODE_EXECUTABLE, MODE_FILE, MODE_SYMLINK};
+use anyhow::Result;
+use std::borrow::Cow;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Files under `repo.join(sub)` as sorted, `/`-separated paths relative to
+/// the repository root. `.rvc` is never entered; an empty `sub` walks the
+/// whole working directory. With `rules`, ignored files are skipped and
+/// ignored directories are not descended into; `sub` itself is always
+/// listed, so a file named explicitly is returned even if it is ignored.
+pub fn list_files(repo: &Path, sub: &str, rules: Option<&IgnoreRules>) -> Result<Vec<Vec<u8>>> {
+    let root = repo.join(sub);
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    let rvc = crate::core::repo_dir(repo);
+
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(&root)
+        .into_iter()
+        .filter_entry(|e| {
+            if e.path() == rvc {
+                return false;
+            }
+            match (rules, e.depth(), e.path().strip_prefix(repo)) {
+                (Some(rules), 1.., Ok(rel)) => !rules.is_ignored(&display_path(&to_repo_path(rel)), e.file_type().is_dir()),
+                _ => true,
+            }
+        })
+        .filter_map(|e| e.ok())
+    {
+        // Symlinks are listed as entries of their own and never followed.
+        if entry.file_type().is_file() || entry.file_type().is_symlink() {
+            if let Ok(rel) = entry.path().strip_prefix(repo) {
+                files.push(to_repo_path(rel));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Joins path components with `/`, the separator used for index and tree
+/// paths. The components' bytes are kept as they are, UTF-8 or not.
+pub fn to_repo_path(rel: &Path) -> Vec<u8> {
+    rel.components()
+        .map(|c| os_str_bytes(c.as_os_str()))
+        .collect::<Vec<_>>()
+        .join(&b'/')
+}
+
+/// The relative filesystem path for an index or tree path.
+pub fn from_repo_path(path: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(path))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(path).into_owned())
+    }
+}
+
+/// A repository path for messages; bytes that are not UTF-8 are replaced.
+pub fn display_path(path: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(path)
+}
+
+#[cfg(unix)]
+fn os_str_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    s.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn os_str_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Deletes a working-tree file and any directories it leaves empty, up to
+/// the repository root. A file that is already gone is not an error.
+pub fn remove_file(repo: &Path, path: &[u8]) -> Result<()> {
+    let abs = repo.join(from_repo_path(path));
+    match std::fs::remove_file(&abs) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let mut dir = abs.parent();
+    while let Some(d) = dir {
+        if d == repo || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+/// Blob id of a working-tree file's current content, or `None` if it is missing.
+pub fn file_oid(repo: &Path, path: &[u8]) -> Result<Option<Oid>> {
+    Ok(file_state(repo, path)?.map(|(oid, _)| oid))
+}
+
+/// Blob id and mode of a working-tree file, or `None` if it is missing.
+pub fn file_state(repo: &Path, path: &[u8]) -> Result<Option<(Oid, u32)>> {
+    let abs = repo.join(from_repo_path(path));
+    match fs::symlink_metadata(&abs) {
+        Ok(meta) if is_tracked_kind(&meta) => {
+            Ok(Some((crate::core::blob_oid_reader(open_content(&abs)?)?, file_mode(&meta))))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether `meta` (from `symlink_metadata`) describes something that can be
+/// tracked: a regular file or a symlink.
+pub fn is_tracked_kind(meta: &fs::Metadata) -> bool {
+    meta.file_type().is_file() || meta.file_type().is_symlink()
+}
+
+/// The tree entry mode for a file, from its `symlink_metadata`.
+pub fn file_mode(meta: &fs::Metadata) -> u32 {
+    if meta.file_type().is_symlink() {
+        return MODE_SYMLINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    MODE_FILE
+}
+
+/// Reads what gets stored as a file's blob: its content, or for a symlink
+/// the path it points to.
+pub fn open_content(abs: &Path) -> Result<Box<dyn Read>> {
+    if fs::symlink_metadata(abs)?.file_type().is_symlink() {
+        return Ok(Box::new(std::io::Cursor::new(link_target_bytes(&fs::read_link(abs)?))));
+    }
+    Ok(Box::new(fs::File::open(abs)?))
+}
+
+#[cfg(unix)]
+fn link_target_bytes(target: &Path) -> Vec<u8> {
+    os_str_bytes(target.as_os_str())
+}
+
+#[cfg(not(unix))]
+fn link_target_bytes(target: &Path) -> Vec<u8> {
+    to_repo_path(target)
+}
+
+/// Writes a blob to the working tree as a file with `mode`, replacing
+/// whatever is at `path`. Symlinks are created as links where the platform
+/// supports them and as plain files holding the target otherwise.
+pub fn write_file(repo: &Path, path: &[u8], mode: u32, content: &mut dyn Read) -> Result<()> {
+    let abs = repo.join(from_repo_path(path));
+    if let Some(parent) = abs.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Never write through an existing link into its target.
+    if fs::symlink_metadata(&abs).is_ok_and(|m| m.file_type().is_symlink() || mode == MODE_SYMLINK) {
+        fs::remove_file(&abs)?;
+    }
+
+    #[cfg(unix)]
+    if mode == MODE_SYMLINK {
+        use std::os::unix::ffi::OsStrExt;
+        let mut target = Vec::new();
+        content.read_to_end(&mut target)?;
+        std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(&target), &abs)?;
+        return Ok(());
+    }
+
+    let mut out = fs::File::create(&abs)?;
+    std::io::copy(content, &mut out)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = out.metadata()?.permissions();
+        let bits = perms.mode();
+        // Executable bits follow the read bits, as umask left them.
+        perms.set_mode(if mode == MODE_EXECUTABLE { bits | ((bits & 0o444) >> 2) } else { bits & !0o111 });
+        out.set_permissions(perms)?;
+    }
+    Ok(())
+}
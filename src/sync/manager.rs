@@ -1,11 +1,11 @@
-use std::collections::HashMap;
 use std::path::Path;
 use libp2p::{PeerId, swarm::{Swarm, SwarmEvent}};
 use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
 use futures::StreamExt;
 use crate::network::behaviour::{RvcBehaviour, RvcEvent};
 use super::messages::{SyncRequest, SyncResponse};
-use crate::repo::sync::{get_local_refs, find_missing_objects, update_refs, store_objects, is_descendant, create_merge_commit};
+use crate::core::FsObjectStore;
+use crate::repo::sync::{get_local_refs, find_missing_objects, update_refs, store_objects, unpack_objects, is_descendant, create_merge_commit, MergeOutcome};
 
 /// Send a request and wait for its response, draining other swarm events meanwhile.
 async fn send_and_wait(
@@ -59,11 +59,13 @@ async fn send_and_wait(
     Err(last_error.unwrap_or_else(|| "All sync attempts failed".into()))
 }
 
+type SyncFuture<'a> = std::pin::Pin<Box<dyn futures::Future<Output = Result<(), Box<dyn std::error::Error>>> + 'a>>;
+
 pub fn sync_with_peer<'a>(
     peer: PeerId,
     cwd: &'a Path,
     swarm: &'a mut Swarm<RvcBehaviour>,
-) -> std::pin::Pin<Box<dyn futures::Future<Output = Result<(), Box<dyn std::error::Error>>> + 'a>> {
+) -> SyncFuture<'a> {
     Box::pin(async move {
         // --- Step 1: Get remote refs ---
         println!("Sending GetRefs request to {:?}", peer);
@@ -76,6 +78,7 @@ pub fn sync_with_peer<'a>(
             println!("  {} -> {}", k, v);
         }
 
+        let store = FsObjectStore::new(cwd);
         let local_refs = get_local_refs(cwd);
         println!("Local refs ({}):", local_refs.len());
         for (k, v) in &local_refs {
@@ -95,7 +98,7 @@ pub fn sync_with_peer<'a>(
         let mut round = 0;
         loop {
             round += 1;
-            let missing = find_missing_objects(cwd, local_refs.clone(), remote_refs.clone());
+            let missing = find_missing_objects(&store, local_refs.clone(), remote_refs.clone());
             println!("Round {}: {} missing objects", round, missing.len());
             
             if missing.is_empty() {
@@ -111,23 +114,36 @@ pub fn sync_with_peer<'a>(
                 println!("  Need: {}", h);
             }
 
-            let fetched = match send_and_wait(swarm, &peer, SyncRequest::GetObjects(missing.clone())).await? {
-                SyncResponse::Objects(objs) => objs,
-                _ => return Err("Unexpected response to GetObjects".into()),
+            let received = match send_and_wait(swarm, &peer, SyncRequest::GetPack(missing.clone())).await? {
+                SyncResponse::Pack { pack, index } => {
+                    println!("Received a pack of {} bytes from remote.", pack.len());
+                    unpack_objects(&store, &pack, &index).map_err(|e| format!("Failed to unpack objects: {:?}", e))?
+                }
+                SyncResponse::Objects(objs) => {
+                    let count = objs.len();
+                    store_objects(&store, objs);
+                    count
+                }
+                _ => return Err("Unexpected response to GetPack".into()),
             };
-            println!("Received {} objects from remote.", fetched.len());
+            println!("Received {} objects from remote.", received);
 
-            if fetched.is_empty() {
+            if received == 0 {
                 println!("Remote returned 0 objects — cannot make progress.");
                 break;
             }
-
-            store_objects(cwd, fetched);
         }
 
         // --- Step 3: Update refs (fast-forward or merge) ---
+        // When HEAD is on a branch the remote also has, that branch's own entry
+        // decides where HEAD ends up.
+        let head_branch = crate::repo::refs::current_branch(cwd).ok().flatten();
+        let old_head = crate::repo::refs::head_commit(cwd).ok().flatten();
         let mut final_refs = local_refs.clone();
         for (ref_name, remote_hash) in &remote_refs {
+            if ref_name == "HEAD" && head_branch.as_ref().is_some_and(|b| remote_refs.contains_key(b)) {
+                continue;
+            }
             match local_refs.get(ref_name) {
                 None => {
                     println!("New ref {}: {}", ref_name, remote_hash);
@@ -137,16 +153,24 @@ pub fn sync_with_peer<'a>(
                     println!("Ref {} already up to date.", ref_name);
                 }
                 Some(local_hash) => {
-                    if is_descendant(cwd, remote_hash, local_hash) {
+                    if is_descendant(&store, remote_hash, local_hash) {
                         println!("Fast-forwarding {} to {}", ref_name, remote_hash);
                         final_refs.insert(ref_name.clone(), remote_hash.clone());
                     } else {
                         println!("Diverged on {}. Creating merge commit...", ref_name);
-                        match create_merge_commit(cwd, local_hash, remote_hash) {
-                            Some(merge_hash) => {
+                        let signature = crate::repo::config::user_signature(cwd)?;
+                        match create_merge_commit(&store, local_hash, remote_hash, &signature) {
+                            Some(MergeOutcome::Merged(merge_hash)) => {
                                 println!("Merge commit: {}", merge_hash);
                                 final_refs.insert(ref_name.clone(), merge_hash);
                             }
+                            Some(MergeOutcome::Conflicts(conflicts)) => {
+                                println!("Merge conflicts on {}:", ref_name);
+                                for conflict in &conflicts {
+                                    println!("  {}", conflict);
+                                }
+                                println!("Kept local {}; reconcile these paths and sync again.", ref_name);
+                            }
                             None => println!("Could not create merge for {}", ref_name),
                         }
                     }
@@ -158,11 +182,18 @@ pub fn sync_with_peer<'a>(
         println!("Refs updated.");
 
         // --- Step 4: Checkout the new HEAD ---
-        if let Some(new_head) = final_refs.get("HEAD") {
+        let new_head = crate::repo::refs::head_commit(cwd).ok().flatten();
+        if let Some(new_head) = new_head.filter(|h| Some(*h) != old_head) {
             println!("Checking out HEAD: {}...", new_head);
-            match crate::commands::checkout::execute(cwd, new_head) {
+            match crate::commands::checkout::switch_trees(cwd, &store, old_head.as_ref(), &new_head, false) {
                 Ok(_) => println!("Checkout successful."),
-                Err(e) => println!("Checkout error: {:?}", e),
+                Err(e) => {
+                    // Keep HEAD matching the working tree; the fetched objects stay
+                    // in the store, so syncing again once the edits are committed is cheap.
+                    update_refs(cwd, &local_refs);
+                    println!("Checkout error: {}", e);
+                    println!("Refs were left unchanged.");
+                }
             }
         }
 
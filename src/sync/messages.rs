@@ -5,10 +5,15 @@ use serde::{Serialize, Deserialize};
 pub enum SyncRequest {
     GetRefs,
     GetObjects(Vec<String>),
+    /// Like `GetObjects`, but answered with a pack so versions of the same
+    /// file travel as deltas against each other.
+    GetPack(Vec<String>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SyncResponse {
     Refs(HashMap<String, String>),
     Objects(Vec<(String, Vec<u8>)>),
+    /// The `.pack` and `.idx` bytes written by `core::pack::write_pack`.
+    Pack { pack: Vec<u8>, index: Vec<u8> },
 }
@@ -3,7 +3,8 @@ pub mod protocol;
 pub mod manager;
 
 use std::path::Path;
-use crate::repo::sync::{get_local_refs, get_objects};
+use crate::core::FsObjectStore;
+use crate::repo::sync::{get_local_refs, get_objects, pack_objects};
 
 pub fn handle_request(repo: &Path, req: messages::SyncRequest) -> messages::SyncResponse {
     println!("Sync request received: {:?}", req);
@@ -15,9 +16,23 @@ pub fn handle_request(repo: &Path, req: messages::SyncRequest) -> messages::Sync
         }
         messages::SyncRequest::GetObjects(hashes) => {
             println!("GetObjects request for {} hashes: {:?}", hashes.len(), hashes);
-            let objects = get_objects(repo, hashes);
+            let objects = get_objects(&FsObjectStore::new(repo), hashes);
             println!("Returning {} found objects.", objects.len());
             messages::SyncResponse::Objects(objects)
         }
+        messages::SyncRequest::GetPack(hashes) => {
+            println!("GetPack request for {} hashes", hashes.len());
+            let store = FsObjectStore::new(repo);
+            match pack_objects(repo, &store, &hashes) {
+                Ok((pack, index)) => {
+                    println!("Returning a pack of {} bytes.", pack.len());
+                    messages::SyncResponse::Pack { pack, index }
+                }
+                Err(e) => {
+                    println!("Warning: Failed to pack objects, sending them whole: {:?}", e);
+                    messages::SyncResponse::Objects(get_objects(&store, hashes))
+                }
+            }
+        }
     }
 }
@@ -17,13 +17,19 @@ impl AsRef<str> for RvcProtocol {
 #[derive(Clone)]
 pub struct RvcCodec;
 
-impl Default for RvcCodec {
-    fn default() -> Self {
+impl RvcCodec {
+    pub fn new() -> Self {
         eprintln!("[Codec] INITIALIZED (Length-Prefixed Mode)");
         Self
     }
 }
 
+impl Default for RvcCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Codec for RvcCodec {
     type Protocol = RvcProtocol;
@@ -1,7 +1,7 @@
-use rvc::core::{FsObjectStore, Object};
+use rvc::core::{FsObjectStore, Object, Signature};
+use rvc::repo::object_store::{MemoryObjectStore, ObjectStore};
 use rvc::index::Index;
 use std::fs;
-use tempfile;
 
 #[test]
 fn test_blob_oid_stability() {
@@ -45,4 +45,1384 @@ fn test_commit_and_log() -> anyhow::Result<()> {
     assert!(!tree_oid.is_empty());
     
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_nested_trees_share_unchanged_subdirs() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let store = FsObjectStore::new(repo);
+    fs::create_dir_all(repo.join("src/util"))?;
+    fs::create_dir_all(repo.join("docs"))?;
+    fs::write(repo.join("src/util/a.txt"), b"a")?;
+    fs::write(repo.join("docs/readme.txt"), b"docs")?;
+    fs::write(repo.join("top.txt"), b"top")?;
+
+    let mut idx = Index::load(repo)?;
+    for p in ["src/util/a.txt", "docs/readme.txt", "top.txt"] {
+        idx.add_file(repo, std::path::Path::new(p), &store)?;
+    }
+    let first = rvc::core::Oid::from_hex(&rvc::core::write_tree(repo, &idx, &store)?)?;
+
+    let root = match store.get(&first)?.expect("root tree") {
+        Object::Tree(entries) => entries,
+        _ => panic!("expected tree"),
+    };
+    let names: Vec<_> = root.iter().map(|e| e.name.as_slice()).collect();
+    assert_eq!(names, [b"docs".as_slice(), b"src", b"top.txt"]);
+    assert!(root[0].is_dir() && root[1].is_dir() && !root[2].is_dir());
+
+    let mut flat: Vec<_> = rvc::core::flatten_tree(&store, &first)?.into_iter().map(|e| e.name).collect();
+    flat.sort();
+    assert_eq!(flat, [b"docs/readme.txt".as_slice(), b"src/util/a.txt", b"top.txt"]);
+
+    fs::write(repo.join("top.txt"), b"changed")?;
+    idx.add_file(repo, std::path::Path::new("top.txt"), &store)?;
+    let second = rvc::core::Oid::from_hex(&rvc::core::write_tree(repo, &idx, &store)?)?;
+    assert_ne!(first, second);
+
+    let root2 = match store.get(&second)?.expect("root tree") {
+        Object::Tree(entries) => entries,
+        _ => panic!("expected tree"),
+    };
+    assert_eq!(root[0].oid, root2[0].oid);
+    assert_eq!(root[1].oid, root2[1].oid);
+    Ok(())
+}
+
+#[test]
+fn test_loose_objects_are_compressed_and_legacy_readable() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let store = FsObjectStore::new(repo);
+    let content = b"line of source text\n".repeat(200);
+    let oid = store.put(&Object::Blob(content.clone()))?;
+
+    let on_disk = fs::read(store.object_path(&oid))?;
+    assert!(on_disk.len() < content.len());
+    assert_eq!(store.get_raw(&oid)?.expect("raw"), [b"blob\0".as_slice(), &content].concat());
+
+    // An uncompressed object written by an older rvc is still readable.
+    let legacy = b"old content".to_vec();
+    let legacy_oid = rvc::core::blob_oid(&legacy);
+    let legacy_path = store.object_path(&legacy_oid);
+    fs::create_dir_all(legacy_path.parent().unwrap())?;
+    fs::write(legacy_path, [b"blob\0".as_slice(), &legacy].concat())?;
+    match store.get(&legacy_oid)?.expect("exists") {
+        Object::Blob(b) => assert_eq!(b, legacy),
+        _ => panic!("expected blob"),
+    }
+
+    // Raw bytes that do not match their OID are rejected.
+    assert!(store.put_raw(&legacy_oid, b"blob\0tampered").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_gc_packs_loose_objects() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let store = FsObjectStore::new(repo);
+    let oids: Vec<_> = (0..20)
+        .map(|i| store.put(&Object::Blob(format!("blob {}", i).into_bytes())))
+        .collect::<anyhow::Result<_>>()?;
+
+    rvc::commands::gc::execute(repo)?;
+
+    let store = FsObjectStore::new(repo);
+    assert!(store.loose_oids()?.is_empty());
+    assert_eq!(store.packed_oids()?.len(), 20);
+    for (i, oid) in oids.iter().enumerate() {
+        match store.get(oid)?.expect("packed object") {
+            Object::Blob(b) => assert_eq!(b, format!("blob {}", i).into_bytes()),
+            _ => panic!("expected blob"),
+        }
+    }
+
+    // A second gc folds new loose objects and the old pack into one pack.
+    let extra = store.put(&Object::Blob(b"late".to_vec()))?;
+    rvc::commands::gc::execute(repo)?;
+    let store = FsObjectStore::new(repo);
+    assert_eq!(store.packed_oids()?.len(), 21);
+    assert!(store.contains(&extra)?);
+    assert_eq!(fs::read_dir(rvc::core::packs_dir(repo))?.count(), 2);
+
+    // Damage inside an entry is caught by rehashing the pack; a trailer that
+    // no longer matches the index is refused on load.
+    let pack = fs::read_dir(rvc::core::packs_dir(repo))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.extension().is_some_and(|x| x == "pack"))
+        .expect("pack written");
+    let idx = pack.with_extension("idx");
+    rvc::core::pack::PackIndex::load(&idx)?.verify()?;
+    let mut bytes = fs::read(&pack)?;
+    bytes[30] ^= 0xff;
+    fs::write(&pack, &bytes)?;
+    assert!(rvc::core::pack::PackIndex::load(&idx)?.verify().is_err());
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&pack, &bytes)?;
+    assert!(rvc::core::pack::PackIndex::load(&idx).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_delta_roundtrip() -> anyhow::Result<()> {
+    let base: Vec<u8> = (0..5000).map(|i| format!("line {}\n", i)).collect::<String>().into_bytes();
+    let mut target = base.clone();
+    target.splice(2000..2010, b"edited here".iter().copied());
+    target.extend_from_slice(b"appended\n");
+
+    let delta = rvc::core::delta::create_delta(&base, &target);
+    assert!(delta.len() < 200);
+    assert_eq!(rvc::core::delta::apply_delta(&base, &delta)?, target);
+    assert_eq!(rvc::core::delta::apply_delta(b"", &rvc::core::delta::create_delta(b"", b"xyz"))?, b"xyz");
+    Ok(())
+}
+
+#[test]
+fn test_gc_stores_file_history_as_deltas() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let mut versions = Vec::new();
+    let mut content: String = (0..4000).map(|i| format!("config_{} = {}\n", i, i)).collect();
+    for round in 0..4 {
+        content.push_str(&format!("round = {}\n", round));
+        fs::write(repo.join("big.cfg"), &content)?;
+        rvc::commands::add::execute(repo, &["big.cfg"], false, false)?;
+        rvc::commands::commit::execute(repo, Some(&format!("round {}", round)), false, false)?;
+        versions.push(rvc::core::blob_oid(content.as_bytes()));
+    }
+
+    rvc::commands::gc::execute(repo)?;
+
+    let pack_size: u64 = fs::read_dir(rvc::core::packs_dir(repo))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x == "pack"))
+        .map(|e| e.metadata().map(|m| m.len()).unwrap_or(0))
+        .sum();
+    let scratch = td.path().join("scratch");
+    rvc::core::init(&scratch)?;
+    let latest = FsObjectStore::new(&scratch);
+    latest.put(&Object::Blob(content.clone().into_bytes()))?;
+    let single = rvc::core::pack::write_pack(&scratch, &latest, &[versions[3]], &Default::default())?;
+    // Four versions cost little more than one stored whole.
+    assert!(pack_size < fs::metadata(single)?.len() * 2);
+
+    let store = FsObjectStore::new(repo);
+    let mut expected: String = (0..4000).map(|i| format!("config_{} = {}\n", i, i)).collect();
+    for (round, oid) in versions.iter().enumerate() {
+        expected.push_str(&format!("round = {}\n", round));
+        match store.get(oid)?.expect("packed blob") {
+            Object::Blob(b) => assert_eq!(b, expected.as_bytes()),
+            _ => panic!("expected blob"),
+        }
+    }
+
+    // The newest version is stored whole and can be streamed out of the pack.
+    use std::io::Read;
+    let mut streamed = String::new();
+    store.open_blob(&versions[3])?.expect("packed blob").read_to_string(&mut streamed)?;
+    assert_eq!(streamed, expected);
+    Ok(())
+}
+
+#[test]
+fn test_sync_transfers_history_as_deltas() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let (remote, local) = (td.path().join("remote"), td.path().join("local"));
+    rvc::core::init(&remote)?;
+    rvc::core::init(&local)?;
+
+    let mut content: String = (0..4000).map(|i| format!("config_{} = {}\n", i, i)).collect();
+    for round in 0..4 {
+        content.push_str(&format!("round = {}\n", round));
+        fs::write(remote.join("big.cfg"), &content)?;
+        rvc::commands::add::execute(&remote, &["big.cfg"], false, false)?;
+        rvc::commands::commit::execute(&remote, Some(&format!("round {}", round)), false, false)?;
+    }
+
+    let remote_store = FsObjectStore::new(&remote);
+    let hashes: Vec<String> = remote_store.loose_oids()?.iter().map(|o| o.to_hex()).collect();
+    let (pack, index) = rvc::repo::sync::pack_objects(&remote, &remote_store, &hashes)?;
+    // Four versions travel in little more than one compressed copy.
+    let latest = [rvc::core::blob_oid(content.as_bytes())];
+    let single = rvc::core::pack::write_pack(&td.path().join("single"), &remote_store, &latest, &Default::default())?;
+    assert!((pack.len() as u64) < fs::metadata(single)?.len() * 2);
+
+    let local_store = FsObjectStore::new(&local);
+    assert_eq!(rvc::repo::sync::unpack_objects(&local_store, &pack, &index)?, hashes.len());
+    for hash in &hashes {
+        let oid = rvc::core::Oid::from_hex(hash)?;
+        assert_eq!(local_store.get_raw(&oid)?, remote_store.get_raw(&oid)?);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unpack_rejects_malformed_packs() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let store = MemoryObjectStore::new();
+
+    // A one-entry pack with valid checksums around whatever entry bytes it is given.
+    let craft = |entry: &[u8]| -> (Vec<u8>, Vec<u8>) {
+        let mut pack = b"RPAK".to_vec();
+        pack.extend_from_slice(&1u32.to_be_bytes());
+        pack.extend_from_slice(&1u32.to_be_bytes());
+        pack.extend_from_slice(entry);
+        let sum = *blake3::hash(&pack).as_bytes();
+        pack.extend_from_slice(&sum);
+        let mut idx = b"RIDX".to_vec();
+        idx.extend_from_slice(&1u32.to_be_bytes());
+        idx.extend_from_slice(&1u32.to_be_bytes());
+        idx.extend_from_slice(rvc::core::blob_oid(b"x").as_bytes());
+        idx.extend_from_slice(&12u64.to_be_bytes());
+        idx.extend_from_slice(&sum);
+        let idx_sum = *blake3::hash(&idx).as_bytes();
+        idx.extend_from_slice(&idx_sum);
+        (pack, idx)
+    };
+    let entry = |kind: u8, raw_len: u64, zlen: u64, data: &[u8]| -> Vec<u8> {
+        let mut e = vec![kind];
+        e.extend_from_slice(&raw_len.to_be_bytes());
+        if kind == 2 {
+            e.extend_from_slice(&[0u8; 32]);
+        }
+        e.extend_from_slice(&zlen.to_be_bytes());
+        e.extend_from_slice(data);
+        e
+    };
+    let mut bomb = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    bomb.write_all(&vec![0u8; 1 << 20])?;
+    let bomb = bomb.finish()?;
+
+    // Lengths past the end of the pack, output larger than recorded and
+    // oversized deltas are all errors rather than huge allocations.
+    for bad in [
+        entry(1, u64::MAX, u64::MAX, b""),
+        entry(1, 10, bomb.len() as u64, &bomb),
+        entry(2, u64::MAX, bomb.len() as u64, &bomb),
+    ] {
+        let (pack, idx) = craft(&bad);
+        assert!(rvc::repo::sync::unpack_objects(&store, &pack, &idx).is_err());
+    }
+
+    // A delta declaring an impossible target size is refused too.
+    let mut delta = vec![0u8];
+    delta.extend_from_slice(&[0xff; 9]);
+    delta.extend_from_slice(&[0x01, 0x01, 0x03, b'a', b'b', b'c']);
+    assert!(rvc::core::delta::apply_delta(b"", &delta).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_loose_objects_fan_out_and_migrate() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let store = FsObjectStore::new(repo);
+    let oid = store.put(&Object::Blob(b"sharded".to_vec()))?;
+    let hex = oid.to_hex();
+    assert!(rvc::core::objects_dir(repo).join(&hex[..2]).join(&hex[2..]).exists());
+
+    // Simulate a repository written before fan-out. The store checks for
+    // the marker once, so a fresh one is needed to see flat objects.
+    fs::remove_file(rvc::core::fanout_marker(repo))?;
+    let store = FsObjectStore::new(repo);
+    let legacy = b"flat".to_vec();
+    let legacy_oid = rvc::core::blob_oid(&legacy);
+    let flat_path = rvc::core::objects_dir(repo).join(legacy_oid.to_hex());
+    fs::write(&flat_path, [b"blob\0".as_slice(), &legacy].concat())?;
+    assert!(store.contains(&legacy_oid)?);
+
+    assert_eq!(rvc::core::migrate_loose_fanout(repo)?, 1);
+    assert!(!flat_path.exists());
+    assert!(store.object_path(&legacy_oid).exists());
+    assert!(rvc::core::fanout_marker(repo).exists());
+    // Once marked, the objects directory is not scanned again.
+    fs::write(&flat_path, [b"blob\0".as_slice(), &legacy].concat())?;
+    assert_eq!(rvc::core::migrate_loose_fanout(repo)?, 0);
+    fs::remove_file(&flat_path)?;
+
+    let mut loose = store.loose_oids()?;
+    loose.sort_by_key(|o| o.to_hex());
+    let mut expected = vec![oid, legacy_oid];
+    expected.sort_by_key(|o| o.to_hex());
+    assert_eq!(loose, expected);
+    Ok(())
+}
+
+#[test]
+fn test_memory_object_store() -> anyhow::Result<()> {
+    let store = MemoryObjectStore::new();
+    let blob = store.put(&Object::Blob(b"in memory".to_vec()))?;
+    let tree = rvc::core::write_tree_from_paths(
+        &store,
+        &[rvc::core::TreeEntry { mode: rvc::core::MODE_FILE, name: b"dir/file.txt".to_vec(), oid: blob }],
+    )?;
+
+    assert!(store.contains(&blob)? && store.contains(&tree)?);
+    assert_eq!(store.list_oids()?.len(), 3);
+    let flat = rvc::core::flatten_tree(&store, &tree)?;
+    assert_eq!(flat.len(), 1);
+    assert_eq!(flat[0].name, b"dir/file.txt");
+    assert_eq!(flat[0].oid, blob);
+
+    // Both backends agree on OIDs and raw encoding.
+    let td = tempfile::tempdir()?;
+    rvc::core::init(td.path())?;
+    let fs_store = FsObjectStore::new(td.path());
+    assert_eq!(fs_store.put(&Object::Blob(b"in memory".to_vec()))?, blob);
+    assert_eq!(fs_store.get_raw(&blob)?, store.get_raw(&blob)?);
+    Ok(())
+}
+
+#[test]
+fn test_streaming_blob_put_and_open() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    let content: Vec<u8> = (0..300_000u32).flat_map(|i| i.to_le_bytes()).collect();
+    let store = FsObjectStore::new(repo);
+    let oid = store.put_blob_stream(&mut content.as_slice())?;
+    assert_eq!(oid, rvc::core::blob_oid(&content));
+    assert_eq!(rvc::core::blob_oid_reader(content.as_slice())?, oid);
+
+    let mut streamed = Vec::new();
+    store.open_blob(&oid)?.expect("blob").read_to_end(&mut streamed)?;
+    assert_eq!(streamed, content);
+
+    // Storing the same content again leaves a single object behind.
+    store.put_blob_stream(&mut content.as_slice())?;
+    assert_eq!(store.loose_oids()?, vec![oid]);
+
+    let tree = store.put(&Object::Tree(Vec::new()))?;
+    assert!(store.open_blob(&tree).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_fsck_reports_corrupt_missing_and_dangling() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    fs::write(repo.join("a.txt"), b"alpha")?;
+    fs::write(repo.join("b.txt"), b"beta")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::add::execute(repo, &["b.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+
+    let store = FsObjectStore::new(repo);
+    let clean = rvc::commands::fsck::check(repo, &store)?;
+    assert!(!clean.has_errors(), "{:?}", clean);
+    assert!(clean.dangling.is_empty());
+
+    let a = rvc::core::blob_oid(b"alpha");
+    let b = rvc::core::blob_oid(b"beta");
+    fs::write(store.object_path(&a), b"blob\0tampered")?;
+    store.remove_loose(&b)?;
+    let stray = store.put(&Object::Blob(b"stray".to_vec()))?;
+
+    let report = rvc::commands::fsck::check(repo, &store)?;
+    assert!(report.has_errors());
+    assert!(report.corrupt.iter().any(|l| l.contains(&a.to_hex())));
+    assert!(report.missing.iter().any(|l| l.contains(&b.to_hex())));
+    assert!(report.dangling.iter().any(|l| l.contains(&stray.to_hex())));
+    assert!(rvc::commands::fsck::execute(repo).is_err());
+
+    // Staged content is reachable from the index even before it is committed.
+    fs::write(repo.join("c.txt"), b"gamma")?;
+    rvc::commands::add::execute(repo, &["c.txt"], false, false)?;
+    let c = rvc::core::blob_oid(b"gamma").to_hex();
+    let report = rvc::commands::fsck::check(repo, &store)?;
+    assert!(!report.dangling.iter().any(|l| l.contains(&c)));
+
+    // Damage inside a pack is found by rehashing the whole pack.
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    fs::write(repo.join("a.txt"), b"alpha")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    rvc::commands::gc::execute(repo)?;
+    let pack = fs::read_dir(rvc::core::packs_dir(repo))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.extension().is_some_and(|x| x == "pack"))
+        .expect("pack written");
+    let mut bytes = fs::read(&pack)?;
+    bytes[30] ^= 0xff;
+    fs::write(&pack, bytes)?;
+    let report = rvc::commands::fsck::check(repo, &FsObjectStore::new(repo))?;
+    assert!(report.corrupt.iter().any(|l| l.starts_with("corrupt pack")), "{:?}", report);
+    Ok(())
+}
+
+#[test]
+fn test_prune_removes_only_unreachable_objects() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    fs::write(repo.join("kept.txt"), b"v1")?;
+    rvc::commands::add::execute(repo, &["kept.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    // Re-adding an edited file leaves the first staged version reachable only via history,
+    // and this one reachable only via the index.
+    fs::write(repo.join("kept.txt"), b"v2")?;
+    rvc::commands::add::execute(repo, &["kept.txt"], false, false)?;
+
+    let store = FsObjectStore::new(repo);
+    let orphan = store.put(&Object::Blob(b"nobody points here".to_vec()))?;
+
+    // Young objects survive the default grace period.
+    assert!(rvc::commands::prune::prune(repo, &store, rvc::commands::prune::DEFAULT_GRACE, false)?.is_empty());
+
+    let listed = rvc::commands::prune::prune(repo, &store, Duration::ZERO, true)?;
+    assert_eq!(listed, vec![orphan]);
+    assert!(store.contains(&orphan)?);
+
+    let removed = rvc::commands::prune::prune(repo, &store, Duration::ZERO, false)?;
+    assert_eq!(removed, vec![orphan]);
+    assert!(!store.contains(&orphan)?);
+    assert!(store.contains(&rvc::core::blob_oid(b"v1"))?);
+    assert!(store.contains(&rvc::core::blob_oid(b"v2"))?);
+    assert!(!rvc::commands::fsck::check(repo, &store)?.has_errors());
+
+    // Blobs are marked reachable without being read: an unreadable one is
+    // still kept rather than failing the walk.
+    let v1 = rvc::core::blob_oid(b"v1");
+    fs::write(store.object_path(&v1), b"not zlib")?;
+    assert!(rvc::commands::prune::reachable_objects(repo, &store)?.contains(&v1));
+    Ok(())
+}
+
+#[test]
+fn test_canonical_commit_encoding() -> anyhow::Result<()> {
+    let commit = rvc::core::Commit {
+        tree: "ab".repeat(32),
+        parents: vec!["cd".repeat(32), "ef".repeat(32)],
+        author: Signature::new("Ada", "ada@example.com", 1_700_000_000, 330),
+        committer: Signature::new("Bob", "bob@example.com", 1_700_000_600, -480),
+        message: "subject\n\nbody with\nlines\n".to_string(),
+    };
+
+    let body = rvc::core::commit_serialize(&commit);
+    let expected = format!(
+        "rvc-commit 2\ntree {}\nparent {}\nparent {}\nauthor Ada <ada@example.com> 1700000000 +0530\ncommitter Bob <bob@example.com> 1700000600 -0800\n\nsubject\n\nbody with\nlines\n",
+        "ab".repeat(32),
+        "cd".repeat(32),
+        "ef".repeat(32)
+    );
+    assert_eq!(String::from_utf8(body.clone())?, expected);
+
+    let back = rvc::core::commit_deserialize(&body)?;
+    assert_eq!(back.parents, commit.parents);
+    assert_eq!(back.message, commit.message);
+    assert_eq!((&back.author, &back.committer), (&commit.author, &commit.committer));
+    assert_eq!(rvc::core::commit_serialize(&back), body);
+
+    // JSON commits written by older versions still decode.
+    let legacy = br#"{"tree":"t","parents":[],"author":"a","message":"m","timestamp":5}"#;
+    let old = rvc::core::commit_deserialize(legacy)?;
+    assert_eq!((old.tree.as_str(), old.author.time), ("t", 5));
+
+    assert!(rvc::core::commit_deserialize(b"rvc-commit 99\ntree t\n\nm").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_config_sets_commit_author() -> anyhow::Result<()> {
+    use rvc::cli::cli::ConfigAction;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    for (key, value) in [("user.name", "Ada Lovelace"), ("user.email", "ada@example.com"), ("sync.timeout", "45")] {
+        rvc::commands::config::execute(
+            repo,
+            &ConfigAction::Set { key: key.to_string(), value: value.to_string(), global: false },
+        )?;
+    }
+
+    let cfg = rvc::repo::config::Config::load(&rvc::repo::config::repo_config_path(repo))?;
+    assert_eq!(cfg.get("user.name"), Some("Ada Lovelace"));
+    assert_eq!(cfg.get("sync.timeout"), Some("45"));
+    assert!(fs::read_to_string(rvc::repo::config::repo_config_path(repo))?.contains("[user]"));
+
+    fs::write(repo.join("f.txt"), b"x")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("authored"), false, false)?;
+
+    let head = rvc::repo::refs::head_commit(repo)?.expect("HEAD commit");
+    match FsObjectStore::new(repo).get(&head)?.expect("commit") {
+        Object::Commit(c) => {
+            assert_eq!(c.author.ident(), "Ada Lovelace <ada@example.com>");
+            assert_eq!(c.committer, c.author);
+        }
+        _ => panic!("expected commit"),
+    }
+
+    assert!(rvc::commands::config::execute(repo, &ConfigAction::Get { key: "user.missing".to_string() }).is_err());
+    assert!(rvc::commands::config::execute(
+        repo,
+        &ConfigAction::Set { key: "nodot".to_string(), value: "v".to_string(), global: false }
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_signature_parse_and_timezone() -> anyhow::Result<()> {
+    let sig = Signature::parse("Grace Hopper <grace@navy.mil> 1700000000 -0330")?;
+    assert_eq!(sig, Signature::new("Grace Hopper", "grace@navy.mil", 1_700_000_000, -210));
+    assert_eq!(sig.to_string(), "Grace Hopper <grace@navy.mil> 1700000000 -0330");
+    assert_eq!(sig.datetime().format("%Y-%m-%d %H:%M %z").to_string(), "2023-11-14 18:43 -0330");
+
+    assert!(Signature::parse("no time here").is_err());
+    assert!(Signature::parse("A <a@b> 5 0530").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_rev_parse_resolves_revisions() -> anyhow::Result<()> {
+    use rvc::repo::revparse::{resolve_commit, resolve_revision};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+    let head = || -> anyhow::Result<rvc::core::Oid> {
+        Ok(rvc::repo::refs::head_commit(repo)?.expect("HEAD commit"))
+    };
+
+    fs::write(repo.join("f.txt"), b"one")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    let first = head()?;
+    fs::write(repo.join("f.txt"), b"two")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("second"), false, false)?;
+    let second = head()?;
+
+    // A merge whose second parent is `first`.
+    let (_, tip) = resolve_commit(repo, &store, "HEAD")?;
+    let merge = store.put(&Object::Commit(rvc::core::Commit {
+        parents: vec![second.to_hex(), first.to_hex()],
+        message: "merge".to_string(),
+        ..tip
+    }))?;
+    rvc::repo::refs::update_head(repo, &merge)?;
+    fs::write(rvc::core::refs_heads_dir(repo).join("feature"), second.to_hex())?;
+
+    assert_eq!(resolve_revision(repo, &store, "HEAD")?, merge);
+    assert_eq!(resolve_revision(repo, &store, "HEAD~")?, second);
+    assert_eq!(resolve_revision(repo, &store, "HEAD~2")?, first);
+    assert_eq!(resolve_revision(repo, &store, "HEAD^2")?, first);
+    assert_eq!(resolve_revision(repo, &store, "HEAD^0")?, merge);
+    assert_eq!(resolve_revision(repo, &store, "feature~1")?, first);
+    assert_eq!(resolve_revision(repo, &store, &merge.to_hex()[..12])?, merge);
+    assert_eq!(resolve_revision(repo, &store, &merge.to_hex().to_uppercase()[..12])?, merge);
+
+    assert!(resolve_revision(repo, &store, "HEAD~3").is_err());
+    assert!(resolve_revision(repo, &store, "HEAD^3").is_err());
+    assert!(resolve_revision(repo, &store, "nope").is_err());
+    assert!(resolve_revision(repo, &store, "HEAD~x").is_err());
+    assert!(resolve_revision(repo, &store, "abc").is_err());
+
+    // Blobs resolve, but are not commits.
+    let blob = rvc::core::blob_oid(b"two").to_hex();
+    assert!(resolve_revision(repo, &store, &blob[..10]).is_ok());
+    assert!(resolve_commit(repo, &store, &blob[..10]).is_err());
+
+    rvc::commands::checkout::execute(repo, "HEAD~2", false)?;
+    assert_eq!(fs::read(repo.join("f.txt"))?, b"one");
+    Ok(())
+}
+
+#[test]
+fn test_branches_and_symbolic_head() -> anyhow::Result<()> {
+    use rvc::cli::cli::BranchAction;
+    use rvc::repo::refs::{self, Head};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    assert_eq!(fs::read_to_string(rvc::core::head_file(repo))?, "ref: refs/heads/main");
+    assert_eq!(refs::head_commit(repo)?, None);
+
+    fs::write(repo.join("f.txt"), b"one")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    let first = refs::read_branch(repo, "main")?.expect("main advanced");
+    assert_eq!(refs::head_commit(repo)?, Some(first));
+
+    // A new branch moves on its own while main stays put.
+    rvc::commands::switch::execute(repo, "feature/x", true, false, false)?;
+    fs::write(repo.join("f.txt"), b"two")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("second"), false, false)?;
+    let second = refs::read_branch(repo, "feature/x")?.expect("feature advanced");
+    assert_eq!(refs::read_branch(repo, "main")?, Some(first));
+    assert_eq!(refs::read_head(repo)?, Head::Branch("feature/x".to_string()));
+
+    rvc::commands::switch::execute(repo, "main", false, false, false)?;
+    assert_eq!(fs::read(repo.join("f.txt"))?, b"one");
+    assert!(rvc::commands::branch::execute(repo, Some(&BranchAction::Delete { name: "main".into(), force: false })).is_err());
+    assert!(rvc::commands::branch::delete(repo, "feature/x", false).is_err());
+
+    rvc::commands::branch::rename(repo, "feature/x", "topic")?;
+    assert_eq!(
+        refs::list_branches(repo)?,
+        vec![("main".to_string(), first), ("topic".to_string(), second)]
+    );
+    assert!(!rvc::core::refs_heads_dir(repo).join("feature").exists());
+
+    // Committing on a detached HEAD leaves every branch alone.
+    rvc::commands::switch::execute(repo, "topic", false, true, false)?;
+    assert_eq!(refs::read_head(repo)?, Head::Detached(second));
+    fs::write(repo.join("f.txt"), b"three")?;
+    rvc::commands::add::execute(repo, &["f.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("detached"), false, false)?;
+    assert_ne!(refs::head_commit(repo)?, Some(second));
+    assert_eq!(refs::read_branch(repo, "topic")?, Some(second));
+
+    rvc::commands::branch::delete(repo, "topic", false)?;
+    for bad in ["", "HEAD", "-x", "a..b", "../x", "a/", "a b", ".hidden"] {
+        assert!(refs::check_branch_name(bad).is_err(), "{:?} should be rejected", bad);
+    }
+
+    // Synced refs go to branches; a bogus name cannot escape refs/heads.
+    refs::set_head_branch(repo, "main")?;
+    let synced = std::collections::HashMap::from([
+        ("HEAD".to_string(), second.to_hex()),
+        ("../../evil".to_string(), second.to_hex()),
+    ]);
+    rvc::repo::sync::update_refs(repo, &synced);
+    assert_eq!(refs::read_branch(repo, "main")?, Some(second));
+    assert!(!repo.join("evil").exists());
+
+    // HEAD files from older versions held a raw hash.
+    let legacy = tempfile::tempdir()?;
+    rvc::core::init(legacy.path())?;
+    fs::write(rvc::core::head_file(legacy.path()), first.to_hex())?;
+    assert!(refs::migrate_legacy_head(legacy.path())?);
+    assert_eq!(refs::read_branch(legacy.path(), "main")?, Some(first));
+    assert_eq!(refs::read_head(legacy.path())?, Head::Branch("main".to_string()));
+    Ok(())
+}
+
+#[test]
+fn test_checkout_is_safe_and_updates_head_and_index() -> anyhow::Result<()> {
+    use rvc::repo::refs::{self, Head};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    fs::create_dir_all(repo.join("dir"))?;
+    fs::write(repo.join("a.txt"), b"1")?;
+    fs::write(repo.join("dir/b.txt"), b"b")?;
+    fs::write(repo.join("keep.txt"), b"k")?;
+    for p in ["a.txt", "dir/b.txt", "keep.txt"] {
+        rvc::commands::add::execute(repo, &[p], false, false)?;
+    }
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    let first = refs::head_commit(repo)?.expect("first");
+
+    fs::write(repo.join("a.txt"), b"2")?;
+    fs::write(repo.join("c.txt"), b"c")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::add::execute(repo, &["c.txt"], false, false)?;
+    let mut idx = Index::load(repo)?;
+    idx.entries.remove(b"dir/b.txt".as_slice());
+    idx.save(repo)?;
+    fs::remove_dir_all(repo.join("dir"))?;
+    rvc::commands::commit::execute(repo, Some("second"), false, false)?;
+
+    // Local edits to files the checkout doesn't touch carry over.
+    fs::write(repo.join("keep.txt"), b"edited")?;
+    rvc::commands::checkout::execute(repo, "HEAD~1", false)?;
+    assert_eq!(refs::read_head(repo)?, Head::Detached(first));
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"1");
+    assert_eq!(fs::read(repo.join("dir/b.txt"))?, b"b");
+    assert!(!repo.join("c.txt").exists());
+    assert_eq!(fs::read(repo.join("keep.txt"))?, b"edited");
+    let idx = Index::load(repo)?;
+    assert!(idx.entries.contains_key(b"dir/b.txt".as_slice()) && !idx.entries.contains_key(b"c.txt".as_slice()));
+    assert_eq!(idx.entries[b"a.txt".as_slice()].oid, rvc::core::blob_oid(b"1").to_hex());
+
+    // An untracked file in the way blocks the checkout and nothing changes.
+    fs::write(repo.join("c.txt"), b"mine")?;
+    let err = rvc::commands::checkout::execute(repo, "main", false).unwrap_err();
+    assert!(err.to_string().contains("c.txt"));
+    assert_eq!(refs::read_head(repo)?, Head::Detached(first));
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"1");
+    fs::remove_file(repo.join("c.txt"))?;
+
+    rvc::commands::checkout::execute(repo, "main", false)?;
+    assert_eq!(refs::read_head(repo)?, Head::Branch("main".to_string()));
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"2");
+    assert!(!repo.join("dir").exists());
+
+    // Untracked files and directories where a file has to be written block
+    // the checkout, even forced, before anything is touched.
+    fs::write(repo.join("dir"), b"untracked")?;
+    let err = rvc::commands::checkout::execute(repo, "HEAD~1", false).unwrap_err();
+    assert!(err.to_string().contains("dir/b.txt"), "{}", err);
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"2");
+    fs::remove_file(repo.join("dir"))?;
+    fs::create_dir_all(repo.join("dir/b.txt"))?;
+    fs::write(repo.join("dir/b.txt/notes"), b"mine")?;
+    assert!(rvc::commands::checkout::execute(repo, "HEAD~1", true).is_err());
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"2");
+    assert_eq!(fs::read(repo.join("dir/b.txt/notes"))?, b"mine");
+    assert_eq!(refs::read_head(repo)?, Head::Branch("main".to_string()));
+    fs::remove_dir_all(repo.join("dir"))?;
+
+    // Modified tracked files are protected unless --force.
+    fs::write(repo.join("a.txt"), b"wip")?;
+    assert!(rvc::commands::checkout::execute(repo, &first.to_hex()[..10], false).is_err());
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"wip");
+    rvc::commands::checkout::execute(repo, &first.to_hex()[..10], true)?;
+    assert_eq!(fs::read(repo.join("a.txt"))?, b"1");
+    assert_eq!(fs::read(repo.join("keep.txt"))?, b"k");
+    Ok(())
+}
+
+#[test]
+fn test_add_directories_globs_and_modes() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let staged = || -> anyhow::Result<Vec<String>> {
+        let mut paths: Vec<String> = Index::load(repo)?.entries.into_keys().map(|k| String::from_utf8(k).unwrap()).collect();
+        paths.sort();
+        Ok(paths)
+    };
+
+    fs::create_dir_all(repo.join("src/deep/er"))?;
+    fs::write(repo.join("src/main.rs"), b"fn main() {}")?;
+    fs::write(repo.join("src/deep/er/lib.rs"), b"// lib")?;
+    fs::write(repo.join("src/notes.txt"), b"n")?;
+    fs::write(repo.join("README.md"), b"r")?;
+    fs::write(repo.join("top.rs"), b"t")?;
+
+    rvc::commands::add::execute(repo, &["src/"], false, false)?;
+    assert_eq!(staged()?, ["src/deep/er/lib.rs", "src/main.rs", "src/notes.txt"]);
+    assert!(staged()?.iter().all(|p| !p.starts_with(".rvc")));
+
+    rvc::commands::add::execute(repo, &["*.rs", "README.md"], false, false)?;
+    assert!(staged()?.contains(&"top.rs".to_string()) && staged()?.contains(&"README.md".to_string()));
+
+    assert!(rvc::commands::add::execute(repo, &["missing.txt"], false, false).is_err());
+    assert!(rvc::commands::add::execute::<&str>(repo, &[], false, false).is_err());
+
+    // -u restages tracked edits and deletions but leaves new files alone.
+    fs::write(repo.join("src/main.rs"), b"fn main() { run() }")?;
+    fs::remove_file(repo.join("src/notes.txt"))?;
+    fs::write(repo.join("new.txt"), b"new")?;
+    rvc::commands::add::execute::<&str>(repo, &[], false, true)?;
+    let idx = Index::load(repo)?;
+    assert_eq!(idx.entries[b"src/main.rs".as_slice()].oid, rvc::core::blob_oid(b"fn main() { run() }").to_hex());
+    assert!(!idx.entries.contains_key(b"src/notes.txt".as_slice()));
+    assert!(!idx.entries.contains_key(b"new.txt".as_slice()));
+
+    // -A picks up everything, including deletions.
+    fs::remove_file(repo.join("top.rs"))?;
+    rvc::commands::add::execute::<&str>(repo, &[], true, false)?;
+    assert_eq!(staged()?, ["README.md", "new.txt", "src/deep/er/lib.rs", "src/main.rs"]);
+
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::commit::execute(repo, Some("bulk"), false, false)?;
+    Ok(())
+}
+
+#[test]
+fn test_rvcignore_rules() -> anyhow::Result<()> {
+    use rvc::repo::ignore::{exclude_file, IgnoreRules};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+
+    fs::write(repo.join(".rvcignore"), "target/\n*.log\n!keep.log\n*.swp\n")?;
+    fs::create_dir_all(repo.join("target/debug"))?;
+    fs::create_dir_all(repo.join("docs/build"))?;
+    fs::write(repo.join("docs/.rvcignore"), "build/\n!*.swp\n")?;
+    fs::create_dir_all(exclude_file(repo).parent().unwrap())?;
+    fs::write(exclude_file(repo), "secret.txt\n")?;
+
+    for (path, body) in [
+        ("target/debug/app", "bin"),
+        ("run.log", "log"),
+        ("keep.log", "keep"),
+        ("a.swp", "swap"),
+        ("docs/b.swp", "swap"),
+        ("docs/build/out.html", "html"),
+        ("docs/index.md", "docs"),
+        ("secret.txt", "shh"),
+        ("main.rs", "code"),
+    ] {
+        fs::write(repo.join(path), body)?;
+    }
+
+    let rules = IgnoreRules::load(repo)?;
+    assert!(rules.is_ignored("target", true));
+    assert!(rules.is_ignored("target/debug/app", false));
+    assert!(!rules.is_ignored("target", false), "directory-only pattern");
+    assert!(rules.is_ignored("run.log", false));
+    assert!(!rules.is_ignored("keep.log", false), "negated pattern");
+    assert!(rules.is_ignored("a.swp", false));
+    assert!(!rules.is_ignored("docs/b.swp", false), "nested file overrides the root");
+    assert!(rules.is_ignored("docs/build/out.html", false));
+    assert!(rules.is_ignored("secret.txt", false), "per-repo exclude file");
+    let m = rules.check("run.log", false).expect("rule");
+    assert_eq!((m.pattern.as_str(), m.source.ends_with(".rvcignore")), ("*.log", true));
+
+    let visible = rvc::repo::worktree::list_files(repo, "", Some(&rules))?;
+    let visible: Vec<_> = visible.iter().map(|p| String::from_utf8_lossy(p)).collect();
+    assert_eq!(visible, [".rvcignore", "docs/.rvcignore", "docs/b.swp", "docs/index.md", "keep.log", "main.rs"]);
+
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    let staged: Vec<Vec<u8>> = Index::load(repo)?.entries.into_keys().collect();
+    assert!(!staged.iter().any(|p| p.starts_with(b"target/") || p == b"run.log" || p == b"secret.txt"));
+
+    // A tracked file stays visible to `add -u` after it becomes ignored.
+    fs::write(repo.join("run.log"), "tracked")?;
+    rvc::commands::add::execute(repo, &["run.log"], false, false)?;
+    fs::write(repo.join("run.log"), "changed")?;
+    rvc::commands::add::execute::<&str>(repo, &[], false, true)?;
+    assert_eq!(Index::load(repo)?.entries[b"run.log".as_slice()].oid, rvc::core::blob_oid(b"changed").to_hex());
+
+    fs::write(repo.join("scratch.txt"), "tmp")?;
+    assert_eq!(rvc::commands::clean::clean(repo, false, true)?, [b"scratch.txt"]);
+    assert!(rvc::commands::clean::execute(repo, false, false, false).is_err());
+    rvc::commands::clean::clean(repo, false, false)?;
+    assert!(!repo.join("scratch.txt").exists() && repo.join("a.swp").exists());
+
+    rvc::commands::clean::clean(repo, true, false)?;
+    assert!(!repo.join("target").exists() && !repo.join("a.swp").exists());
+    assert!(repo.join("run.log").exists() && repo.join("main.rs").exists());
+
+    rvc::commands::check_ignore::execute(repo, &["run.log", "keep.log", "target/"], true)?;
+    Ok(())
+}
+
+#[test]
+fn test_rm_and_deletions_in_status() -> anyhow::Result<()> {
+    use rvc::commands::status::get_status;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    fs::create_dir_all(repo.join("dir"))?;
+    for (p, body) in [("a.txt", "a"), ("b.txt", "b"), ("c.txt", "c"), ("dir/d.txt", "d")] {
+        fs::write(repo.join(p), body)?;
+    }
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::commit::execute(repo, Some("all"), false, false)?;
+
+    // Deleted on disk only: an unstaged deletion that is still committed as-is.
+    fs::remove_file(repo.join("a.txt"))?;
+    let status = get_status(repo, &store)?;
+    assert_eq!(status.unstaged_changes, ["deleted: a.txt"]);
+    assert!(status.staged_changes.is_empty());
+
+    rvc::commands::rm::execute(repo, &["a.txt"], false, false, false)?;
+    rvc::commands::rm::execute(repo, &["b.txt"], true, false, false)?;
+    assert!(repo.join("b.txt").exists());
+    assert!(rvc::commands::rm::execute(repo, &["dir"], false, false, false).is_err());
+    assert!(rvc::commands::rm::execute(repo, &["nope.txt"], false, false, false).is_err());
+
+    // Local edits are protected unless --cached or -f.
+    fs::write(repo.join("c.txt"), "edited")?;
+    assert!(rvc::commands::rm::execute(repo, &["c.txt"], false, false, false).is_err());
+    assert!(repo.join("c.txt").exists());
+
+    rvc::commands::rm::execute(repo, &["dir"], false, true, false)?;
+    assert!(!repo.join("dir").exists());
+
+    let status = get_status(repo, &store)?;
+    assert_eq!(status.staged_changes, ["deleted: a.txt", "deleted: b.txt", "deleted: dir/d.txt"]);
+    assert_eq!(status.untracked_files, ["b.txt"]);
+
+    rvc::commands::commit::execute(repo, Some("remove"), false, false)?;
+    let (_, commit) = rvc::repo::revparse::resolve_commit(repo, &store, "HEAD")?;
+    let files: Vec<Vec<u8>> = rvc::core::flatten_tree(&store, &rvc::core::Oid::from_hex(&commit.tree)?)?
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(files, [b"c.txt"]);
+
+    rvc::commands::rm::execute(repo, &["c.txt"], false, false, true)?;
+    rvc::commands::commit::execute(repo, Some("empty"), false, false)?;
+    assert!(get_status(repo, &store)?.staged_changes.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_mv_and_rename_detection() -> anyhow::Result<()> {
+    use rvc::commands::status::get_status;
+    use rvc::repo::refs;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    let body: String = (1..=40).map(|i| format!("line {}\n", i)).collect();
+    fs::create_dir_all(repo.join("lib"))?;
+    fs::write(repo.join("a.txt"), &body)?;
+    fs::write(repo.join("notes.md"), body.replace("line", "note"))?;
+    fs::write(repo.join("lib/x.rs"), "x")?;
+    fs::write(repo.join("lib/y.rs"), "y")?;
+    fs::write(repo.join("old.txt"), "old")?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::commit::execute(repo, Some("base"), false, false)?;
+    let base = refs::head_commit(repo)?.expect("base");
+
+    rvc::commands::mv::execute(repo, &["a.txt"], "b.txt", false)?;
+    rvc::commands::mv::execute(repo, &["lib"], "src", false)?;
+    assert!(rvc::commands::mv::execute(repo, &["untracked"], "z", false).is_err());
+    assert!(rvc::commands::mv::execute(repo, &["b.txt"], "notes.md", false).is_err());
+    assert!(repo.join("b.txt").exists() && !repo.join("a.txt").exists());
+    assert!(repo.join("src/x.rs").exists() && !repo.join("lib").exists());
+
+    // Destinations are resolved against the root and checked before any move.
+    for dest in ["../escaped.txt", "src/../../escaped.txt", ".rvc/old.txt", "/tmp/old.txt"] {
+        assert!(rvc::commands::mv::execute(repo, &["old.txt"], dest, false).is_err(), "{}", dest);
+    }
+    fs::create_dir_all(repo.join("other"))?;
+    fs::write(repo.join("other/x.rs"), "other x")?;
+    rvc::commands::add::execute(repo, &["other/x.rs"], false, false)?;
+    assert!(rvc::commands::mv::execute(repo, &["src/x.rs", "other/x.rs"], ".", false).is_err());
+    assert!(repo.join("src/x.rs").exists() && repo.join("other/x.rs").exists());
+    rvc::commands::mv::execute(repo, &["./other/x.rs"], "./", false)?;
+    assert!(repo.join("x.rs").exists() && !repo.join("other/x.rs").exists());
+    rvc::commands::mv::execute(repo, &["x.rs"], "src/../moved.rs", false)?;
+    assert!(repo.join("moved.rs").exists());
+    rvc::commands::rm::execute(repo, &["moved.rs"], false, false, true)?;
+
+    // A rename with small edits is found by similarity.
+    fs::rename(repo.join("notes.md"), repo.join("NOTES.md"))?;
+    fs::write(repo.join("NOTES.md"), body.replace("line", "note").replace("note 7\n", "note seven\n"))?;
+    rvc::commands::add::execute::<&str>(repo, &[], true, false)?;
+
+    let status = get_status(repo, &store)?;
+    assert_eq!(
+        status.staged_changes,
+        [
+            "renamed: a.txt -> b.txt",
+            "renamed: lib/x.rs -> src/x.rs",
+            "renamed: lib/y.rs -> src/y.rs",
+            "renamed: notes.md -> NOTES.md",
+        ]
+    );
+    assert!(status.unstaged_changes.is_empty());
+    rvc::commands::diff::execute(repo)?;
+    rvc::commands::commit::execute(repo, Some("renames"), false, false)?;
+    let local = refs::head_commit(repo)?.expect("local");
+
+    // The other side edits a.txt at its old path and deletes a file.
+    rvc::commands::branch::create(repo, "other", &base.to_hex())?;
+    rvc::commands::switch::execute(repo, "other", false, false, false)?;
+    fs::write(repo.join("a.txt"), body.replace("line 40\n", "line forty\n"))?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::rm::execute(repo, &["old.txt"], false, false, false)?;
+    rvc::commands::commit::execute(repo, Some("edit"), false, false)?;
+    let remote = refs::head_commit(repo)?.expect("remote");
+
+    assert_eq!(rvc::repo::sync::merge_base(&store, &local, &remote), Some(base));
+    let signature = rvc::core::Signature::new("M", "m@example.com", 0, 0);
+    let merged = match rvc::repo::sync::create_merge_commit(&store, &local.to_hex(), &remote.to_hex(), &signature) {
+        Some(rvc::repo::sync::MergeOutcome::Merged(oid)) => oid,
+        other => panic!("expected a clean merge, got {:?}", other),
+    };
+    let (_, commit) = rvc::repo::revparse::resolve_commit(repo, &store, &merged)?;
+    let mut files: Vec<(String, rvc::core::Oid)> =
+        rvc::core::flatten_tree(&store, &rvc::core::Oid::from_hex(&commit.tree)?)?
+            .into_iter()
+            .map(|e| (String::from_utf8(e.name).unwrap(), e.oid))
+            .collect();
+    files.sort();
+    let names: Vec<&str> = files.iter().map(|(n, _)| n.as_str()).collect();
+    // The deletion applies and the edit follows the rename.
+    assert_eq!(names, ["NOTES.md", "b.txt", "src/x.rs", "src/y.rs"]);
+    assert_eq!(files[1].1, rvc::core::blob_oid(body.replace("line 40\n", "line forty\n").as_bytes()));
+    Ok(())
+}
+
+#[test]
+fn test_sync_merge_reports_conflicts() -> anyhow::Result<()> {
+    use rvc::repo::refs;
+    use rvc::repo::sync::{create_merge_commit, MergeConflict, MergeOutcome};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+    let body = |tag: &str| -> String { (1..=40).map(|i| format!("{} line {}\n", tag, i)).collect() };
+
+    fs::write(repo.join("a.txt"), body("a"))?;
+    fs::write(repo.join("both.txt"), "base")?;
+    fs::write(repo.join("gone.txt"), body("gone"))?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::commit::execute(repo, Some("base"), false, false)?;
+    let base = refs::head_commit(repo)?.expect("base");
+
+    // Local renames a.txt to b.txt, renames gone.txt and edits both.txt.
+    rvc::commands::mv::execute(repo, &["a.txt"], "b.txt", false)?;
+    rvc::commands::mv::execute(repo, &["gone.txt"], "kept.txt", false)?;
+    fs::write(repo.join("both.txt"), "local")?;
+    rvc::commands::add::execute(repo, &["both.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("local"), false, false)?;
+    let local = refs::head_commit(repo)?.expect("local");
+
+    // Remote renames a.txt to c.txt, deletes gone.txt and edits both.txt.
+    rvc::commands::branch::create(repo, "other", &base.to_hex())?;
+    rvc::commands::switch::execute(repo, "other", false, false, false)?;
+    rvc::commands::mv::execute(repo, &["a.txt"], "c.txt", false)?;
+    rvc::commands::rm::execute(repo, &["gone.txt"], false, false, false)?;
+    fs::write(repo.join("both.txt"), "remote")?;
+    rvc::commands::add::execute(repo, &["both.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("remote"), false, false)?;
+    let remote = refs::head_commit(repo)?.expect("remote");
+
+    let signature = rvc::core::Signature::new("M", "m@example.com", 0, 0);
+    let Some(MergeOutcome::Conflicts(mut conflicts)) =
+        create_merge_commit(&store, &local.to_hex(), &remote.to_hex(), &signature)
+    else {
+        panic!("expected conflicts");
+    };
+    conflicts.sort_by_key(|c| c.to_string());
+    assert_eq!(
+        conflicts,
+        [
+            MergeConflict::Content("both.txt".into()),
+            MergeConflict::RenameDelete { from: "gone.txt".into(), to: "kept.txt".into() },
+            MergeConflict::RenameRename { from: "a.txt".into(), local: "b.txt".into(), remote: "c.txt".into() },
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_status_uses_cached_stat_data() -> anyhow::Result<()> {
+    use rvc::commands::status::get_status;
+    use std::time::{Duration, SystemTime};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    fs::write(repo.join("a.txt"), "one")?;
+    fs::write(repo.join("b.txt"), "two")?;
+    // b.txt looks modified after the index is written, so it is racily clean.
+    fs::File::options().write(true).open(repo.join("b.txt"))?
+        .set_modified(SystemTime::now() + Duration::from_secs(3600))?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+
+    // Point both entries at other content: only a rehash can notice.
+    let mut idx = Index::load(repo)?;
+    #[cfg(unix)]
+    assert!(idx.entries[b"a.txt".as_slice()].ino != 0 && idx.entries[b"a.txt".as_slice()].ctime != 0);
+    let bogus = rvc::core::blob_oid(b"bogus").to_hex();
+    for entry in idx.entries.values_mut() {
+        entry.oid = bogus.clone();
+    }
+    idx.save(repo)?;
+    let status = get_status(repo, &store)?;
+    assert_eq!(status.unstaged_changes, ["modified: b.txt"]);
+
+    // Once the stat data no longer matches, the file is hashed again.
+    let file = fs::File::options().write(true).open(repo.join("a.txt"))?;
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))?;
+    let status = get_status(repo, &store)?;
+    assert_eq!(status.unstaged_changes, ["modified: a.txt", "modified: b.txt"]);
+
+    // A file that hashes unchanged gets its stat data refreshed.
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000))?;
+    assert!(!get_status(repo, &store)?.unstaged_changes.contains(&"modified: a.txt".to_string()));
+    assert_eq!(Index::load(repo)?.entries[b"a.txt".as_slice()].mtime, 2_000_000);
+    Ok(())
+}
+
+#[test]
+fn test_binary_index_format_and_json_upgrade() -> anyhow::Result<()> {
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    fs::write(repo.join("b.txt"), "b")?;
+    fs::write(repo.join("a.txt"), "a")?;
+    let oid = rvc::core::blob_oid(b"a").to_hex();
+    let legacy = format!(
+        r#"{{"entries":{{"a.txt":{{"path":"a.txt","oid":"{}","mode":33188,"mtime":5,"size":1}}}}}}"#,
+        oid
+    );
+    let index_path = rvc::core::index_file(repo);
+    fs::write(&index_path, legacy)?;
+
+    // JSON indexes still load and are rewritten in the binary format.
+    assert!(rvc::index::migrate_legacy_index(repo)?);
+    assert!(!rvc::index::migrate_legacy_index(repo)?);
+    assert_eq!(&fs::read(&index_path)?[..4], b"RVCI");
+    let mut idx = Index::load(repo)?;
+    assert_eq!(idx.entries[b"a.txt".as_slice()].oid, oid);
+    assert_eq!(idx.entries[b"a.txt".as_slice()].mtime, 5);
+
+    // Entries come back sorted; optional extensions survive a rewrite.
+    idx.add_file(repo, std::path::Path::new("b.txt"), &store)?;
+    idx.extensions.push(rvc::index::IndexExtension { signature: *b"TEST", data: b"keep".to_vec() });
+    idx.save(repo)?;
+    let idx = Index::load(repo)?;
+    assert_eq!(idx.entries.keys().collect::<Vec<_>>(), [b"a.txt", b"b.txt"]);
+    assert_eq!(idx.extensions[0].data, b"keep");
+
+    // A flipped byte fails the checksum.
+    let mut raw = fs::read(&index_path)?;
+    raw[20] ^= 1;
+    fs::write(&index_path, &raw)?;
+    assert!(Index::load(repo).is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_executable_and_symlink_modes() -> anyhow::Result<()> {
+    use rvc::commands::status::get_status;
+    use rvc::core::{MODE_EXECUTABLE, MODE_FILE, MODE_SYMLINK};
+    use std::os::unix::fs::PermissionsExt;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    fs::write(repo.join("run.sh"), "#!/bin/sh\n")?;
+    fs::set_permissions(repo.join("run.sh"), fs::Permissions::from_mode(0o755))?;
+    fs::write(repo.join("target.txt"), "target")?;
+    std::os::unix::fs::symlink("target.txt", repo.join("link"))?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+
+    let idx = Index::load(repo)?;
+    assert_eq!(idx.entries[b"run.sh".as_slice()].mode, MODE_EXECUTABLE);
+    assert_eq!(idx.entries[b"target.txt".as_slice()].mode, MODE_FILE);
+    assert_eq!(idx.entries[b"link".as_slice()].mode, MODE_SYMLINK);
+    assert_eq!(idx.entries[b"link".as_slice()].oid, rvc::core::blob_oid(b"target.txt").to_hex());
+    rvc::commands::commit::execute(repo, Some("modes"), false, false)?;
+    let base = rvc::repo::refs::head_commit(repo)?.expect("base");
+
+    // A permission change alone is reported, unstaged and then staged.
+    fs::set_permissions(repo.join("run.sh"), fs::Permissions::from_mode(0o644))?;
+    assert_eq!(get_status(repo, &store)?.unstaged_changes, ["mode changed: run.sh (100755 -> 100644)"]);
+    rvc::commands::diff::execute(repo)?;
+    rvc::commands::add::execute(repo, &["run.sh"], false, false)?;
+    let status = get_status(repo, &store)?;
+    assert_eq!(status.staged_changes, ["mode changed: run.sh (100755 -> 100644)"]);
+    assert!(status.unstaged_changes.is_empty());
+    rvc::commands::commit::execute(repo, Some("not executable"), false, false)?;
+
+    // Checkout restores the executable bit and the link itself.
+    fs::remove_file(repo.join("link"))?;
+    fs::write(repo.join("link"), "target.txt")?;
+    rvc::commands::checkout::execute(repo, &base.to_hex(), true)?;
+    assert_ne!(fs::metadata(repo.join("run.sh"))?.permissions().mode() & 0o111, 0);
+    assert_eq!(fs::read_link(repo.join("link"))?, std::path::Path::new("target.txt"));
+    assert!(get_status(repo, &store)?.unstaged_changes.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_tree_encoding_handles_any_file_name() -> anyhow::Result<()> {
+    use rvc::core::{tree_deserialize, tree_serialize, TreeEntry, MODE_FILE};
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    let oid = rvc::core::blob_oid(b"x");
+    let names = ["with space", "line\nbreak", "trailing ", "ünïcødé"];
+    let entries: Vec<TreeEntry> = names.iter().map(|n| TreeEntry { mode: MODE_FILE, name: n.as_bytes().to_vec(), oid }).collect();
+    let tree = store.put(&Object::Tree(entries))?;
+    let Some(Object::Tree(read)) = store.get(&tree)? else { panic!("expected tree") };
+    let mut got: Vec<Vec<u8>> = read.into_iter().map(|e| e.name).collect();
+    got.sort();
+    let mut want: Vec<Vec<u8>> = names.iter().map(|n| n.as_bytes().to_vec()).collect();
+    want.sort();
+    assert_eq!(got, want);
+
+    // Trees written by earlier versions stay readable, spaces included.
+    let legacy = format!("{} my notes.txt {}\n", MODE_FILE, oid.to_hex());
+    let read = tree_deserialize(legacy.as_bytes())?;
+    assert_eq!((read[0].name.as_slice(), read[0].oid), (b"my notes.txt".as_slice(), oid));
+    assert!(tree_serialize(&read).starts_with(b"rvc-tree 2\n"));
+    let err = tree_deserialize(b"rvc-tree 9\n").unwrap_err();
+    assert!(err.to_string().contains("unsupported tree version 9"), "{}", err);
+
+    // The names survive a commit and checkout of the working tree.
+    fs::write(repo.join("a file.txt"), "one")?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::commit::execute(repo, Some("spaces"), false, false)?;
+    fs::remove_file(repo.join("a file.txt"))?;
+    rvc::commands::checkout::execute(repo, "HEAD", true)?;
+    assert_eq!(fs::read_to_string(repo.join("a file.txt"))?, "one");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_file_names_round_trip() -> anyhow::Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let name = OsStr::from_bytes(b"\xff.txt");
+    fs::write(repo.join(name), "raw bytes")?;
+
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    assert!(Index::load(repo)?.entries.contains_key(b"\xff.txt".as_slice()));
+    rvc::commands::commit::execute(repo, Some("bytes"), false, false)?;
+
+    let store = FsObjectStore::new(repo);
+    let (_, commit) = rvc::repo::revparse::resolve_commit(repo, &store, "HEAD")?;
+    let files = rvc::core::flatten_tree(&store, &rvc::core::Oid::from_hex(&commit.tree)?)?;
+    assert_eq!(files[0].name, b"\xff.txt");
+
+    fs::remove_file(repo.join(name))?;
+    rvc::commands::checkout::execute(repo, "HEAD", true)?;
+    assert_eq!(fs::read_to_string(repo.join(name))?, "raw bytes");
+    Ok(())
+}
+
+#[test]
+fn test_restore_and_reset() -> anyhow::Result<()> {
+    use rvc::commands::reset::{self, ResetMode};
+    use rvc::commands::status::get_status;
+    use rvc::repo::refs;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    fs::write(repo.join("a.txt"), "one")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("first"), false, false)?;
+    let first = refs::head_commit(repo)?.expect("first");
+    fs::write(repo.join("a.txt"), "two")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    rvc::commands::commit::execute(repo, Some("second"), false, false)?;
+    let second = refs::head_commit(repo)?.expect("second");
+
+    // Unstage an edit and a new file, then throw the edit away.
+    fs::write(repo.join("a.txt"), "three")?;
+    fs::write(repo.join("new.txt"), "new")?;
+    rvc::commands::add::execute(repo, &["."], false, false)?;
+    rvc::commands::restore::execute(repo, &["a.txt", "new.txt"], true, None)?;
+    let status = get_status(repo, &store)?;
+    assert!(status.staged_changes.is_empty());
+    assert_eq!(status.unstaged_changes, ["modified: a.txt"]);
+    assert_eq!(status.untracked_files, ["new.txt"]);
+    rvc::commands::restore::execute(repo, &["a.txt"], false, None)?;
+    assert_eq!(fs::read_to_string(repo.join("a.txt"))?, "two");
+    assert!(rvc::commands::restore::execute(repo, &["missing.txt"], false, None).is_err());
+
+    // --source takes the content from another commit, leaving the index.
+    rvc::commands::restore::execute(repo, &["a.txt"], false, Some("HEAD~1"))?;
+    assert_eq!(fs::read_to_string(repo.join("a.txt"))?, "one");
+    assert_eq!(get_status(repo, &store)?.unstaged_changes, ["modified: a.txt"]);
+    rvc::commands::restore::execute(repo, &["a.txt"], false, None)?;
+
+    // --soft moves only the branch; --mixed also the index; --hard everything.
+    reset::execute(repo, &first.to_hex(), ResetMode::Soft)?;
+    assert_eq!(refs::read_branch(repo, "main")?, Some(first));
+    assert_eq!(get_status(repo, &store)?.staged_changes, ["modified: a.txt"]);
+    reset::execute(repo, &second.to_hex(), ResetMode::Soft)?;
+    reset::execute(repo, &first.to_hex(), ResetMode::Mixed)?;
+    let status = get_status(repo, &store)?;
+    assert!(status.staged_changes.is_empty());
+    assert_eq!(status.unstaged_changes, ["modified: a.txt"]);
+    assert_eq!(fs::read_to_string(repo.join("a.txt"))?, "two");
+    fs::write(repo.join("staged.txt"), "only in the index")?;
+    rvc::commands::add::execute(repo, &["staged.txt"], false, false)?;
+    reset::execute(repo, "HEAD", ResetMode::Hard)?;
+    assert_eq!(fs::read_to_string(repo.join("a.txt"))?, "one");
+    assert!(!repo.join("staged.txt").exists());
+    assert!(get_status(repo, &store)?.untracked_files.iter().all(|p| p != "staged.txt"));
+    assert!(get_status(repo, &store)?.unstaged_changes.is_empty());
+    assert_eq!(refs::current_branch(repo)?.as_deref(), Some("main"));
+    Ok(())
+}
+
+#[test]
+fn test_commit_amend_empty_guard_and_editor() -> anyhow::Result<()> {
+    use rvc::commands::commit;
+    use rvc::repo::refs;
+    use rvc::repo::revparse::resolve_commit;
+
+    let td = tempfile::tempdir()?;
+    let repo = td.path();
+    rvc::core::init(repo)?;
+    let store = FsObjectStore::new(repo);
+
+    // An empty root commit and a commit that changes nothing are refused.
+    assert!(commit::execute(repo, Some("empty"), false, false).is_err());
+    fs::write(repo.join("a.txt"), "a")?;
+    rvc::commands::add::execute(repo, &["a.txt"], false, false)?;
+    commit::execute(repo, Some("first"), false, false)?;
+    let first = refs::head_commit(repo)?.expect("first");
+    assert!(commit::execute(repo, Some("again"), false, false).is_err());
+    assert!(commit::execute(repo, Some("   "), true, false).is_err());
+    commit::execute(repo, Some("marker"), true, false)?;
+    let marker = refs::head_commit(repo)?.expect("marker");
+
+    // Amending replaces the tip with the same parents and author.
+    fs::write(repo.join("b.txt"), "b")?;
+    rvc::commands::add::execute(repo, &["b.txt"], false, false)?;
+    commit::execute(repo, Some("second"), false, true)?;
+    let (amended_oid, amended) = resolve_commit(repo, &store, "HEAD")?;
+    let (_, original) = resolve_commit(repo, &store, &marker.to_hex())?;
+    assert_ne!(amended_oid, marker);
+    assert_eq!(amended.parents, [first.to_hex()]);
+    assert_eq!(amended.author, original.author);
+    assert_eq!(amended.message, "second");
+
+    // Without a message the editor writes it; comment lines are dropped.
+    #[cfg(unix)]
+    {
+        std::env::set_var("RVC_EDITOR", "printf '# note\\nfrom the editor\\n' >");
+        commit::execute(repo, None, true, true)?;
+        std::env::remove_var("RVC_EDITOR");
+        let (_, edited) = resolve_commit(repo, &store, "HEAD")?;
+        assert_eq!(edited.message, "from the editor\n");
+        assert_eq!(edited.parents, [first.to_hex()]);
+    }
+    Ok(())
+}